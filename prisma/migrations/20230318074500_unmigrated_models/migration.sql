@@ -0,0 +1,248 @@
+/*
+  Warnings:
+
+  - Catches the migration history up with the models that were added to the schema without a migration.
+  - The columns `full_name` and `roles_id` of `User` are renamed to `nickname` and `role_id`, their values are kept.
+
+*/
+-- AlterEnum
+-- This migration adds more than one value to an enum.
+-- With PostgreSQL versions 11 and earlier, this is not possible
+-- in a single migration. This can be worked around by creating
+-- multiple migrations, each migration adding only one value to
+-- the enum.
+
+
+ALTER TYPE "Module" ADD VALUE 'ASSET';
+ALTER TYPE "Module" ADD VALUE 'LOCATION';
+ALTER TYPE "Module" ADD VALUE 'ADMIN';
+ALTER TYPE "Module" ADD VALUE 'MaintainanceRequest';
+
+-- AlterTable
+ALTER TABLE "User" RENAME COLUMN "full_name" TO "nickname";
+
+-- AlterTable
+ALTER TABLE "User" RENAME COLUMN "roles_id" TO "role_id";
+
+-- RenameForeignKey
+ALTER TABLE "User" RENAME CONSTRAINT "User_roles_id_fkey" TO "User_role_id_fkey";
+
+-- CreateTable
+CREATE TABLE "Asset" (
+    "id" SERIAL NOT NULL,
+    "created_at" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "updated_at" TIMESTAMP(3) NOT NULL,
+    "deleted_at" TIMESTAMP(3),
+    "asset_code" VARCHAR(255) NOT NULL,
+    "asset_name" VARCHAR(511) NOT NULL,
+    "asset_description" TEXT NOT NULL,
+    "asset_location_id" INTEGER NOT NULL,
+    "asset_status_id" INTEGER NOT NULL,
+    "asset_id" INTEGER,
+    "customize_fileds_1" VARCHAR(255),
+    "customize_fileds_2" VARCHAR(255),
+    "customize_fileds_3" VARCHAR(255),
+    "customize_fileds_4" VARCHAR(255),
+    "customize_fileds_5" VARCHAR(255),
+    "company_id" INTEGER NOT NULL,
+
+    CONSTRAINT "Asset_pkey" PRIMARY KEY ("id")
+);
+
+-- CreateTable
+CREATE TABLE "AssetStatus" (
+    "id" SERIAL NOT NULL,
+    "created_at" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "updated_at" TIMESTAMP(3) NOT NULL,
+    "deleted_at" TIMESTAMP(3),
+    "status_code" VARCHAR(255) NOT NULL,
+    "status_name" VARCHAR(255) NOT NULL,
+    "company_id" INTEGER NOT NULL,
+
+    CONSTRAINT "AssetStatus_pkey" PRIMARY KEY ("id")
+);
+
+-- CreateTable
+CREATE TABLE "AssetLocation" (
+    "id" SERIAL NOT NULL,
+    "created_at" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "updated_at" TIMESTAMP(3) NOT NULL,
+    "deleted_at" TIMESTAMP(3),
+    "location_code" VARCHAR(255) NOT NULL,
+    "location_name" VARCHAR(255) NOT NULL,
+    "location_description" TEXT NOT NULL,
+    "parent_id" INTEGER,
+    "company_id" INTEGER NOT NULL,
+
+    CONSTRAINT "AssetLocation_pkey" PRIMARY KEY ("id")
+);
+
+-- CreateTable
+CREATE TABLE "MaintainanceRequest" (
+    "id" SERIAL NOT NULL,
+    "created_at" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "updated_at" TIMESTAMP(3) NOT NULL,
+    "deleted_at" TIMESTAMP(3),
+    "asset_id" INTEGER NOT NULL,
+    "mr_name" VARCHAR(255) NOT NULL,
+    "user_id" INTEGER NOT NULL,
+    "mr_status_id" INTEGER NOT NULL,
+    "mr_category_id" INTEGER NOT NULL,
+    "mr_priority_id" INTEGER NOT NULL,
+    "mr_failure_impact_id" INTEGER NOT NULL,
+    "mr_failure_mode_id" INTEGER NOT NULL,
+    "mr_error_code" VARCHAR(255) NOT NULL,
+    "mr_description" TEXT NOT NULL,
+    "company_id" INTEGER NOT NULL,
+
+    CONSTRAINT "MaintainanceRequest_pkey" PRIMARY KEY ("id")
+);
+
+-- CreateTable
+CREATE TABLE "MrStatus" (
+    "id" SERIAL NOT NULL,
+    "created_at" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "updated_at" TIMESTAMP(3) NOT NULL,
+    "deleted_at" TIMESTAMP(3),
+    "status_code" VARCHAR(255) NOT NULL,
+    "status_name" VARCHAR(255) NOT NULL,
+    "company_id" INTEGER NOT NULL,
+
+    CONSTRAINT "MrStatus_pkey" PRIMARY KEY ("id")
+);
+
+-- CreateTable
+CREATE TABLE "MrCategory" (
+    "id" SERIAL NOT NULL,
+    "created_at" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "updated_at" TIMESTAMP(3) NOT NULL,
+    "deleted_at" TIMESTAMP(3),
+    "category_code" VARCHAR(255) NOT NULL,
+    "category_name" VARCHAR(255) NOT NULL,
+    "company_id" INTEGER NOT NULL,
+
+    CONSTRAINT "MrCategory_pkey" PRIMARY KEY ("id")
+);
+
+-- CreateTable
+CREATE TABLE "MrPriority" (
+    "id" SERIAL NOT NULL,
+    "created_at" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "updated_at" TIMESTAMP(3) NOT NULL,
+    "deleted_at" TIMESTAMP(3),
+    "priority_code" VARCHAR(255) NOT NULL,
+    "priority_name" VARCHAR(255) NOT NULL,
+    "company_id" INTEGER NOT NULL,
+
+    CONSTRAINT "MrPriority_pkey" PRIMARY KEY ("id")
+);
+
+-- CreateTable
+CREATE TABLE "MrFailureImpact" (
+    "id" SERIAL NOT NULL,
+    "created_at" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "updated_at" TIMESTAMP(3) NOT NULL,
+    "deleted_at" TIMESTAMP(3),
+    "failure_impact_code" VARCHAR(255) NOT NULL,
+    "failure_impact_name" VARCHAR(255) NOT NULL,
+    "company_id" INTEGER NOT NULL,
+
+    CONSTRAINT "MrFailureImpact_pkey" PRIMARY KEY ("id")
+);
+
+-- CreateTable
+CREATE TABLE "MrFailureMode" (
+    "id" SERIAL NOT NULL,
+    "created_at" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "updated_at" TIMESTAMP(3) NOT NULL,
+    "deleted_at" TIMESTAMP(3),
+    "failure_mode_code" VARCHAR(255) NOT NULL,
+    "failure_mode_name" VARCHAR(255) NOT NULL,
+    "company_id" INTEGER NOT NULL,
+
+    CONSTRAINT "MrFailureMode_pkey" PRIMARY KEY ("id")
+);
+
+-- CreateIndex
+CREATE UNIQUE INDEX "Asset_asset_code_key" ON "Asset"("asset_code");
+
+-- CreateIndex
+CREATE UNIQUE INDEX "AssetStatus_status_code_key" ON "AssetStatus"("status_code");
+
+-- CreateIndex
+CREATE UNIQUE INDEX "AssetLocation_location_code_key" ON "AssetLocation"("location_code");
+
+-- CreateIndex
+CREATE UNIQUE INDEX "MrStatus_status_code_key" ON "MrStatus"("status_code");
+
+-- CreateIndex
+CREATE UNIQUE INDEX "MrCategory_category_code_key" ON "MrCategory"("category_code");
+
+-- CreateIndex
+CREATE UNIQUE INDEX "MrPriority_priority_code_key" ON "MrPriority"("priority_code");
+
+-- CreateIndex
+CREATE UNIQUE INDEX "MrFailureImpact_failure_impact_code_key" ON "MrFailureImpact"("failure_impact_code");
+
+-- CreateIndex
+CREATE UNIQUE INDEX "MrFailureMode_failure_mode_code_key" ON "MrFailureMode"("failure_mode_code");
+
+-- AddForeignKey
+ALTER TABLE "Asset" ADD CONSTRAINT "Asset_company_id_fkey" FOREIGN KEY ("company_id") REFERENCES "Company"("id") ON DELETE RESTRICT ON UPDATE CASCADE;
+
+-- AddForeignKey
+ALTER TABLE "Asset" ADD CONSTRAINT "Asset_asset_location_id_fkey" FOREIGN KEY ("asset_location_id") REFERENCES "AssetLocation"("id") ON DELETE RESTRICT ON UPDATE CASCADE;
+
+-- AddForeignKey
+ALTER TABLE "Asset" ADD CONSTRAINT "Asset_asset_status_id_fkey" FOREIGN KEY ("asset_status_id") REFERENCES "AssetStatus"("id") ON DELETE RESTRICT ON UPDATE CASCADE;
+
+-- AddForeignKey
+ALTER TABLE "Asset" ADD CONSTRAINT "Asset_asset_id_fkey" FOREIGN KEY ("asset_id") REFERENCES "Asset"("id") ON DELETE SET NULL ON UPDATE CASCADE;
+
+-- AddForeignKey
+ALTER TABLE "AssetStatus" ADD CONSTRAINT "AssetStatus_company_id_fkey" FOREIGN KEY ("company_id") REFERENCES "Company"("id") ON DELETE RESTRICT ON UPDATE CASCADE;
+
+-- AddForeignKey
+ALTER TABLE "AssetLocation" ADD CONSTRAINT "AssetLocation_company_id_fkey" FOREIGN KEY ("company_id") REFERENCES "Company"("id") ON DELETE RESTRICT ON UPDATE CASCADE;
+
+-- AddForeignKey
+ALTER TABLE "AssetLocation" ADD CONSTRAINT "AssetLocation_parent_id_fkey" FOREIGN KEY ("parent_id") REFERENCES "AssetLocation"("id") ON DELETE SET NULL ON UPDATE CASCADE;
+
+-- AddForeignKey
+ALTER TABLE "MaintainanceRequest" ADD CONSTRAINT "MaintainanceRequest_company_id_fkey" FOREIGN KEY ("company_id") REFERENCES "Company"("id") ON DELETE RESTRICT ON UPDATE CASCADE;
+
+-- AddForeignKey
+ALTER TABLE "MaintainanceRequest" ADD CONSTRAINT "MaintainanceRequest_asset_id_fkey" FOREIGN KEY ("asset_id") REFERENCES "Asset"("id") ON DELETE RESTRICT ON UPDATE CASCADE;
+
+-- AddForeignKey
+ALTER TABLE "MaintainanceRequest" ADD CONSTRAINT "MaintainanceRequest_user_id_fkey" FOREIGN KEY ("user_id") REFERENCES "User"("id") ON DELETE RESTRICT ON UPDATE CASCADE;
+
+-- AddForeignKey
+ALTER TABLE "MaintainanceRequest" ADD CONSTRAINT "MaintainanceRequest_mr_status_id_fkey" FOREIGN KEY ("mr_status_id") REFERENCES "MrStatus"("id") ON DELETE RESTRICT ON UPDATE CASCADE;
+
+-- AddForeignKey
+ALTER TABLE "MaintainanceRequest" ADD CONSTRAINT "MaintainanceRequest_mr_category_id_fkey" FOREIGN KEY ("mr_category_id") REFERENCES "MrCategory"("id") ON DELETE RESTRICT ON UPDATE CASCADE;
+
+-- AddForeignKey
+ALTER TABLE "MaintainanceRequest" ADD CONSTRAINT "MaintainanceRequest_mr_priority_id_fkey" FOREIGN KEY ("mr_priority_id") REFERENCES "MrPriority"("id") ON DELETE RESTRICT ON UPDATE CASCADE;
+
+-- AddForeignKey
+ALTER TABLE "MaintainanceRequest" ADD CONSTRAINT "MaintainanceRequest_mr_failure_impact_id_fkey" FOREIGN KEY ("mr_failure_impact_id") REFERENCES "MrFailureImpact"("id") ON DELETE RESTRICT ON UPDATE CASCADE;
+
+-- AddForeignKey
+ALTER TABLE "MaintainanceRequest" ADD CONSTRAINT "MaintainanceRequest_mr_failure_mode_id_fkey" FOREIGN KEY ("mr_failure_mode_id") REFERENCES "MrFailureMode"("id") ON DELETE RESTRICT ON UPDATE CASCADE;
+
+-- AddForeignKey
+ALTER TABLE "MrStatus" ADD CONSTRAINT "MrStatus_company_id_fkey" FOREIGN KEY ("company_id") REFERENCES "Company"("id") ON DELETE RESTRICT ON UPDATE CASCADE;
+
+-- AddForeignKey
+ALTER TABLE "MrCategory" ADD CONSTRAINT "MrCategory_company_id_fkey" FOREIGN KEY ("company_id") REFERENCES "Company"("id") ON DELETE RESTRICT ON UPDATE CASCADE;
+
+-- AddForeignKey
+ALTER TABLE "MrPriority" ADD CONSTRAINT "MrPriority_company_id_fkey" FOREIGN KEY ("company_id") REFERENCES "Company"("id") ON DELETE RESTRICT ON UPDATE CASCADE;
+
+-- AddForeignKey
+ALTER TABLE "MrFailureImpact" ADD CONSTRAINT "MrFailureImpact_company_id_fkey" FOREIGN KEY ("company_id") REFERENCES "Company"("id") ON DELETE RESTRICT ON UPDATE CASCADE;
+
+-- AddForeignKey
+ALTER TABLE "MrFailureMode" ADD CONSTRAINT "MrFailureMode_company_id_fkey" FOREIGN KEY ("company_id") REFERENCES "Company"("id") ON DELETE RESTRICT ON UPDATE CASCADE;
@@ -0,0 +1,14 @@
+/*
+  Warnings:
+
+  - Role names become unique per company instead of globally. Existing rows already satisfy it since the names were globally unique.
+
+*/
+-- DropIndex
+DROP INDEX "Role_role_name_key";
+
+-- AlterTable
+ALTER TABLE "Role" ADD COLUMN     "is_default" BOOLEAN NOT NULL DEFAULT false;
+
+-- CreateIndex
+CREATE UNIQUE INDEX "Role_company_id_role_name_key" ON "Role"("company_id", "role_name");
@@ -0,0 +1,54 @@
+/*
+  Warnings:
+
+  - Business codes become unique per company instead of globally.
+  - A unique constraint covering the columns `[company_id, <code>]` is added on every lookup table. Existing rows already satisfy it since the codes were globally unique.
+
+*/
+-- DropIndex
+DROP INDEX IF EXISTS "Asset_asset_code_key";
+
+-- DropIndex
+DROP INDEX IF EXISTS "AssetStatus_status_code_key";
+
+-- DropIndex
+DROP INDEX IF EXISTS "AssetLocation_location_code_key";
+
+-- DropIndex
+DROP INDEX IF EXISTS "MrStatus_status_code_key";
+
+-- DropIndex
+DROP INDEX IF EXISTS "MrCategory_category_code_key";
+
+-- DropIndex
+DROP INDEX IF EXISTS "MrPriority_priority_code_key";
+
+-- DropIndex
+DROP INDEX IF EXISTS "MrFailureImpact_failure_impact_code_key";
+
+-- DropIndex
+DROP INDEX IF EXISTS "MrFailureMode_failure_mode_code_key";
+
+-- CreateIndex
+CREATE UNIQUE INDEX "Asset_company_id_asset_code_key" ON "Asset"("company_id", "asset_code");
+
+-- CreateIndex
+CREATE UNIQUE INDEX "AssetStatus_company_id_status_code_key" ON "AssetStatus"("company_id", "status_code");
+
+-- CreateIndex
+CREATE UNIQUE INDEX "AssetLocation_company_id_location_code_key" ON "AssetLocation"("company_id", "location_code");
+
+-- CreateIndex
+CREATE UNIQUE INDEX "MrStatus_company_id_status_code_key" ON "MrStatus"("company_id", "status_code");
+
+-- CreateIndex
+CREATE UNIQUE INDEX "MrCategory_company_id_category_code_key" ON "MrCategory"("company_id", "category_code");
+
+-- CreateIndex
+CREATE UNIQUE INDEX "MrPriority_company_id_priority_code_key" ON "MrPriority"("company_id", "priority_code");
+
+-- CreateIndex
+CREATE UNIQUE INDEX "MrFailureImpact_company_id_failure_impact_code_key" ON "MrFailureImpact"("company_id", "failure_impact_code");
+
+-- CreateIndex
+CREATE UNIQUE INDEX "MrFailureMode_company_id_failure_mode_code_key" ON "MrFailureMode"("company_id", "failure_mode_code");
@@ -0,0 +1,123 @@
+/*
+  Warnings:
+
+  - You are about to drop the columns `customize_fileds_1` to `customize_fileds_5` on the `User`, `Company` and `Asset` tables. Their values are moved into the new `custom_fields` column first.
+  - Every company gets a TEXT custom field definition for each of those columns it actually used.
+
+*/
+-- CreateEnum
+CREATE TYPE "CustomFieldEntity" AS ENUM ('USER', 'COMPANY', 'ASSET');
+
+-- CreateEnum
+CREATE TYPE "CustomFieldType" AS ENUM ('TEXT', 'NUMBER', 'DATE', 'ENUM', 'BOOLEAN');
+
+-- CreateTable
+CREATE TABLE "CustomFieldDefinition" (
+    "id" SERIAL NOT NULL,
+    "created_at" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "updated_at" TIMESTAMP(3) NOT NULL,
+    "deleted_at" TIMESTAMP(3),
+    "company_id" INTEGER NOT NULL,
+    "entity" "CustomFieldEntity" NOT NULL,
+    "field_key" VARCHAR(255) NOT NULL,
+    "label" VARCHAR(255) NOT NULL,
+    "field_type" "CustomFieldType" NOT NULL,
+    "required" BOOLEAN NOT NULL DEFAULT false,
+    "options" TEXT[],
+    "position" INTEGER NOT NULL DEFAULT 0,
+
+    CONSTRAINT "CustomFieldDefinition_pkey" PRIMARY KEY ("id")
+);
+
+-- CreateIndex
+CREATE UNIQUE INDEX "CustomFieldDefinition_company_id_entity_field_key_key" ON "CustomFieldDefinition"("company_id", "entity", "field_key");
+
+-- AddForeignKey
+ALTER TABLE "CustomFieldDefinition" ADD CONSTRAINT "CustomFieldDefinition_company_id_fkey" FOREIGN KEY ("company_id") REFERENCES "Company"("id") ON DELETE RESTRICT ON UPDATE CASCADE;
+
+-- AlterTable
+ALTER TABLE "User" ADD COLUMN     "custom_fields" JSONB NOT NULL DEFAULT '{}';
+
+-- AlterTable
+ALTER TABLE "Company" ADD COLUMN     "custom_fields" JSONB NOT NULL DEFAULT '{}';
+
+-- AlterTable
+ALTER TABLE "Asset" ADD COLUMN     "custom_fields" JSONB NOT NULL DEFAULT '{}';
+
+-- MigrateData
+INSERT INTO "CustomFieldDefinition" ("updated_at", "company_id", "entity", "field_key", "label", "field_type", "options", "position")
+SELECT DISTINCT CURRENT_TIMESTAMP, "company_id", 'USER'::"CustomFieldEntity", 'custom_field_1', 'Custom field 1', 'TEXT'::"CustomFieldType", '{}'::text[], 1 FROM "User" WHERE "customize_fileds_1" IS NOT NULL;
+INSERT INTO "CustomFieldDefinition" ("updated_at", "company_id", "entity", "field_key", "label", "field_type", "options", "position")
+SELECT DISTINCT CURRENT_TIMESTAMP, "company_id", 'USER'::"CustomFieldEntity", 'custom_field_2', 'Custom field 2', 'TEXT'::"CustomFieldType", '{}'::text[], 2 FROM "User" WHERE "customize_fileds_2" IS NOT NULL;
+INSERT INTO "CustomFieldDefinition" ("updated_at", "company_id", "entity", "field_key", "label", "field_type", "options", "position")
+SELECT DISTINCT CURRENT_TIMESTAMP, "company_id", 'USER'::"CustomFieldEntity", 'custom_field_3', 'Custom field 3', 'TEXT'::"CustomFieldType", '{}'::text[], 3 FROM "User" WHERE "customize_fileds_3" IS NOT NULL;
+INSERT INTO "CustomFieldDefinition" ("updated_at", "company_id", "entity", "field_key", "label", "field_type", "options", "position")
+SELECT DISTINCT CURRENT_TIMESTAMP, "company_id", 'USER'::"CustomFieldEntity", 'custom_field_4', 'Custom field 4', 'TEXT'::"CustomFieldType", '{}'::text[], 4 FROM "User" WHERE "customize_fileds_4" IS NOT NULL;
+INSERT INTO "CustomFieldDefinition" ("updated_at", "company_id", "entity", "field_key", "label", "field_type", "options", "position")
+SELECT DISTINCT CURRENT_TIMESTAMP, "company_id", 'USER'::"CustomFieldEntity", 'custom_field_5', 'Custom field 5', 'TEXT'::"CustomFieldType", '{}'::text[], 5 FROM "User" WHERE "customize_fileds_5" IS NOT NULL;
+UPDATE "User" SET "custom_fields" = jsonb_strip_nulls(jsonb_build_object(
+    'custom_field_1', "customize_fileds_1",
+    'custom_field_2', "customize_fileds_2",
+    'custom_field_3', "customize_fileds_3",
+    'custom_field_4', "customize_fileds_4",
+    'custom_field_5', "customize_fileds_5"
+));
+
+-- MigrateData
+INSERT INTO "CustomFieldDefinition" ("updated_at", "company_id", "entity", "field_key", "label", "field_type", "options", "position")
+SELECT DISTINCT CURRENT_TIMESTAMP, "id", 'COMPANY'::"CustomFieldEntity", 'custom_field_1', 'Custom field 1', 'TEXT'::"CustomFieldType", '{}'::text[], 1 FROM "Company" WHERE "customize_fileds_1" IS NOT NULL;
+INSERT INTO "CustomFieldDefinition" ("updated_at", "company_id", "entity", "field_key", "label", "field_type", "options", "position")
+SELECT DISTINCT CURRENT_TIMESTAMP, "id", 'COMPANY'::"CustomFieldEntity", 'custom_field_2', 'Custom field 2', 'TEXT'::"CustomFieldType", '{}'::text[], 2 FROM "Company" WHERE "customize_fileds_2" IS NOT NULL;
+INSERT INTO "CustomFieldDefinition" ("updated_at", "company_id", "entity", "field_key", "label", "field_type", "options", "position")
+SELECT DISTINCT CURRENT_TIMESTAMP, "id", 'COMPANY'::"CustomFieldEntity", 'custom_field_3', 'Custom field 3', 'TEXT'::"CustomFieldType", '{}'::text[], 3 FROM "Company" WHERE "customize_fileds_3" IS NOT NULL;
+INSERT INTO "CustomFieldDefinition" ("updated_at", "company_id", "entity", "field_key", "label", "field_type", "options", "position")
+SELECT DISTINCT CURRENT_TIMESTAMP, "id", 'COMPANY'::"CustomFieldEntity", 'custom_field_4', 'Custom field 4', 'TEXT'::"CustomFieldType", '{}'::text[], 4 FROM "Company" WHERE "customize_fileds_4" IS NOT NULL;
+INSERT INTO "CustomFieldDefinition" ("updated_at", "company_id", "entity", "field_key", "label", "field_type", "options", "position")
+SELECT DISTINCT CURRENT_TIMESTAMP, "id", 'COMPANY'::"CustomFieldEntity", 'custom_field_5', 'Custom field 5', 'TEXT'::"CustomFieldType", '{}'::text[], 5 FROM "Company" WHERE "customize_fileds_5" IS NOT NULL;
+UPDATE "Company" SET "custom_fields" = jsonb_strip_nulls(jsonb_build_object(
+    'custom_field_1', "customize_fileds_1",
+    'custom_field_2', "customize_fileds_2",
+    'custom_field_3', "customize_fileds_3",
+    'custom_field_4', "customize_fileds_4",
+    'custom_field_5', "customize_fileds_5"
+));
+
+-- MigrateData
+INSERT INTO "CustomFieldDefinition" ("updated_at", "company_id", "entity", "field_key", "label", "field_type", "options", "position")
+SELECT DISTINCT CURRENT_TIMESTAMP, "company_id", 'ASSET'::"CustomFieldEntity", 'custom_field_1', 'Custom field 1', 'TEXT'::"CustomFieldType", '{}'::text[], 1 FROM "Asset" WHERE "customize_fileds_1" IS NOT NULL;
+INSERT INTO "CustomFieldDefinition" ("updated_at", "company_id", "entity", "field_key", "label", "field_type", "options", "position")
+SELECT DISTINCT CURRENT_TIMESTAMP, "company_id", 'ASSET'::"CustomFieldEntity", 'custom_field_2', 'Custom field 2', 'TEXT'::"CustomFieldType", '{}'::text[], 2 FROM "Asset" WHERE "customize_fileds_2" IS NOT NULL;
+INSERT INTO "CustomFieldDefinition" ("updated_at", "company_id", "entity", "field_key", "label", "field_type", "options", "position")
+SELECT DISTINCT CURRENT_TIMESTAMP, "company_id", 'ASSET'::"CustomFieldEntity", 'custom_field_3', 'Custom field 3', 'TEXT'::"CustomFieldType", '{}'::text[], 3 FROM "Asset" WHERE "customize_fileds_3" IS NOT NULL;
+INSERT INTO "CustomFieldDefinition" ("updated_at", "company_id", "entity", "field_key", "label", "field_type", "options", "position")
+SELECT DISTINCT CURRENT_TIMESTAMP, "company_id", 'ASSET'::"CustomFieldEntity", 'custom_field_4', 'Custom field 4', 'TEXT'::"CustomFieldType", '{}'::text[], 4 FROM "Asset" WHERE "customize_fileds_4" IS NOT NULL;
+INSERT INTO "CustomFieldDefinition" ("updated_at", "company_id", "entity", "field_key", "label", "field_type", "options", "position")
+SELECT DISTINCT CURRENT_TIMESTAMP, "company_id", 'ASSET'::"CustomFieldEntity", 'custom_field_5', 'Custom field 5', 'TEXT'::"CustomFieldType", '{}'::text[], 5 FROM "Asset" WHERE "customize_fileds_5" IS NOT NULL;
+UPDATE "Asset" SET "custom_fields" = jsonb_strip_nulls(jsonb_build_object(
+    'custom_field_1', "customize_fileds_1",
+    'custom_field_2', "customize_fileds_2",
+    'custom_field_3', "customize_fileds_3",
+    'custom_field_4', "customize_fileds_4",
+    'custom_field_5', "customize_fileds_5"
+));
+
+-- AlterTable
+ALTER TABLE "User" DROP COLUMN "customize_fileds_1",
+DROP COLUMN "customize_fileds_2",
+DROP COLUMN "customize_fileds_3",
+DROP COLUMN "customize_fileds_4",
+DROP COLUMN "customize_fileds_5";
+
+-- AlterTable
+ALTER TABLE "Company" DROP COLUMN "customize_fileds_1",
+DROP COLUMN "customize_fileds_2",
+DROP COLUMN "customize_fileds_3",
+DROP COLUMN "customize_fileds_4",
+DROP COLUMN "customize_fileds_5";
+
+-- AlterTable
+ALTER TABLE "Asset" DROP COLUMN "customize_fileds_1",
+DROP COLUMN "customize_fileds_2",
+DROP COLUMN "customize_fileds_3",
+DROP COLUMN "customize_fileds_4",
+DROP COLUMN "customize_fileds_5";
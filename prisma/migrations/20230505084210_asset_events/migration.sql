@@ -0,0 +1,28 @@
+-- CreateEnum
+CREATE TYPE "AssetEventType" AS ENUM ('CREATED', 'STATUS_CHANGED', 'LOCATION_CHANGED', 'PARENT_CHANGED');
+
+-- CreateTable
+CREATE TABLE "AssetEvent" (
+    "id" SERIAL NOT NULL,
+    "created_at" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "company_id" INTEGER NOT NULL,
+    "asset_id" INTEGER NOT NULL,
+    "user_id" INTEGER NOT NULL,
+    "event_type" "AssetEventType" NOT NULL,
+    "from_id" INTEGER,
+    "to_id" INTEGER,
+
+    CONSTRAINT "AssetEvent_pkey" PRIMARY KEY ("id")
+);
+
+-- CreateIndex
+CREATE INDEX "AssetEvent_asset_id_created_at_idx" ON "AssetEvent"("asset_id", "created_at");
+
+-- AddForeignKey
+ALTER TABLE "AssetEvent" ADD CONSTRAINT "AssetEvent_company_id_fkey" FOREIGN KEY ("company_id") REFERENCES "Company"("id") ON DELETE RESTRICT ON UPDATE CASCADE;
+
+-- AddForeignKey
+ALTER TABLE "AssetEvent" ADD CONSTRAINT "AssetEvent_asset_id_fkey" FOREIGN KEY ("asset_id") REFERENCES "Asset"("id") ON DELETE CASCADE ON UPDATE CASCADE;
+
+-- AddForeignKey
+ALTER TABLE "AssetEvent" ADD CONSTRAINT "AssetEvent_user_id_fkey" FOREIGN KEY ("user_id") REFERENCES "User"("id") ON DELETE RESTRICT ON UPDATE CASCADE;
@@ -0,0 +1,18 @@
+/*
+  Warnings:
+
+  - Existing maintenance requests start out OPEN and are considered opened when they were created.
+
+*/
+-- CreateEnum
+CREATE TYPE "MrState" AS ENUM ('OPEN', 'ACKNOWLEDGED', 'RESOLVED', 'CLOSED', 'CANCELLED');
+
+-- AlterTable
+ALTER TABLE "MaintainanceRequest" ADD COLUMN     "acknowledged_at" TIMESTAMP(3),
+ADD COLUMN     "closed_at" TIMESTAMP(3),
+ADD COLUMN     "mr_state" "MrState" NOT NULL DEFAULT 'OPEN',
+ADD COLUMN     "opened_at" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+ADD COLUMN     "resolved_at" TIMESTAMP(3);
+
+-- MigrateData
+UPDATE "MaintainanceRequest" SET "opened_at" = "created_at";
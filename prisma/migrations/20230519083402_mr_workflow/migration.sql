@@ -0,0 +1,53 @@
+/*
+  Warnings:
+
+  - Existing mr statuses are typed OPEN, set the type of the others before defining transitions.
+
+*/
+-- CreateEnum
+CREATE TYPE "MrStatusType" AS ENUM ('OPEN', 'IN_PROGRESS', 'ON_HOLD', 'RESOLVED', 'CLOSED');
+
+-- AlterTable
+ALTER TABLE "MrStatus" ADD COLUMN     "status_type" "MrStatusType" NOT NULL DEFAULT 'OPEN';
+
+-- CreateTable
+CREATE TABLE "MrStatusTransition" (
+    "id" SERIAL NOT NULL,
+    "created_at" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "updated_at" TIMESTAMP(3) NOT NULL,
+    "company_id" INTEGER NOT NULL,
+    "from_status_id" INTEGER NOT NULL,
+    "to_status_id" INTEGER NOT NULL,
+
+    CONSTRAINT "MrStatusTransition_pkey" PRIMARY KEY ("id")
+);
+
+-- CreateTable
+CREATE TABLE "_MrStatusTransitionToRole" (
+    "A" INTEGER NOT NULL,
+    "B" INTEGER NOT NULL
+);
+
+-- CreateIndex
+CREATE UNIQUE INDEX "MrStatusTransition_from_status_id_to_status_id_key" ON "MrStatusTransition"("from_status_id", "to_status_id");
+
+-- CreateIndex
+CREATE UNIQUE INDEX "_MrStatusTransitionToRole_AB_unique" ON "_MrStatusTransitionToRole"("A", "B");
+
+-- CreateIndex
+CREATE INDEX "_MrStatusTransitionToRole_B_index" ON "_MrStatusTransitionToRole"("B");
+
+-- AddForeignKey
+ALTER TABLE "MrStatusTransition" ADD CONSTRAINT "MrStatusTransition_company_id_fkey" FOREIGN KEY ("company_id") REFERENCES "Company"("id") ON DELETE RESTRICT ON UPDATE CASCADE;
+
+-- AddForeignKey
+ALTER TABLE "MrStatusTransition" ADD CONSTRAINT "MrStatusTransition_from_status_id_fkey" FOREIGN KEY ("from_status_id") REFERENCES "MrStatus"("id") ON DELETE CASCADE ON UPDATE CASCADE;
+
+-- AddForeignKey
+ALTER TABLE "MrStatusTransition" ADD CONSTRAINT "MrStatusTransition_to_status_id_fkey" FOREIGN KEY ("to_status_id") REFERENCES "MrStatus"("id") ON DELETE CASCADE ON UPDATE CASCADE;
+
+-- AddForeignKey
+ALTER TABLE "_MrStatusTransitionToRole" ADD CONSTRAINT "_MrStatusTransitionToRole_A_fkey" FOREIGN KEY ("A") REFERENCES "MrStatusTransition"("id") ON DELETE CASCADE ON UPDATE CASCADE;
+
+-- AddForeignKey
+ALTER TABLE "_MrStatusTransitionToRole" ADD CONSTRAINT "_MrStatusTransitionToRole_B_fkey" FOREIGN KEY ("B") REFERENCES "Role"("id") ON DELETE CASCADE ON UPDATE CASCADE;
@@ -0,0 +1,5 @@
+-- AlterTable
+ALTER TABLE "MaintainanceRequest" ADD COLUMN     "assignee_id" INTEGER;
+
+-- AddForeignKey
+ALTER TABLE "MaintainanceRequest" ADD CONSTRAINT "MaintainanceRequest_assignee_id_fkey" FOREIGN KEY ("assignee_id") REFERENCES "User"("id") ON DELETE SET NULL ON UPDATE CASCADE;
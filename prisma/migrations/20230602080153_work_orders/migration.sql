@@ -0,0 +1,103 @@
+-- AlterEnum
+ALTER TYPE "Module" ADD VALUE 'WORK_ORDER';
+
+-- CreateEnum
+CREATE TYPE "WoState" AS ENUM ('PLANNED', 'IN_PROGRESS', 'ON_HOLD', 'COMPLETED', 'CANCELLED');
+
+-- CreateTable
+CREATE TABLE "WorkOrder" (
+    "id" SERIAL NOT NULL,
+    "created_at" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "updated_at" TIMESTAMP(3) NOT NULL,
+    "deleted_at" TIMESTAMP(3),
+    "company_id" INTEGER NOT NULL,
+    "mr_id" INTEGER,
+    "asset_id" INTEGER NOT NULL,
+    "wo_name" VARCHAR(255) NOT NULL,
+    "wo_description" TEXT NOT NULL,
+    "wo_state" "WoState" NOT NULL DEFAULT 'PLANNED',
+    "planned_start" TIMESTAMP(3),
+    "planned_end" TIMESTAMP(3),
+    "started_at" TIMESTAMP(3),
+    "completed_at" TIMESTAMP(3),
+    "completion_notes" TEXT,
+
+    CONSTRAINT "WorkOrder_pkey" PRIMARY KEY ("id")
+);
+
+-- CreateTable
+CREATE TABLE "WoTask" (
+    "id" SERIAL NOT NULL,
+    "created_at" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "updated_at" TIMESTAMP(3) NOT NULL,
+    "work_order_id" INTEGER NOT NULL,
+    "description" VARCHAR(511) NOT NULL,
+    "done_at" TIMESTAMP(3),
+
+    CONSTRAINT "WoTask_pkey" PRIMARY KEY ("id")
+);
+
+-- CreateTable
+CREATE TABLE "WoLabour" (
+    "id" SERIAL NOT NULL,
+    "created_at" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "work_order_id" INTEGER NOT NULL,
+    "user_id" INTEGER NOT NULL,
+    "started_at" TIMESTAMP(3) NOT NULL,
+    "minutes" INTEGER NOT NULL,
+    "note" TEXT,
+
+    CONSTRAINT "WoLabour_pkey" PRIMARY KEY ("id")
+);
+
+-- CreateTable
+CREATE TABLE "WoPart" (
+    "id" SERIAL NOT NULL,
+    "created_at" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "work_order_id" INTEGER NOT NULL,
+    "part_code" VARCHAR(255) NOT NULL,
+    "part_name" VARCHAR(255) NOT NULL,
+    "quantity" DOUBLE PRECISION NOT NULL,
+    "unit" VARCHAR(63),
+
+    CONSTRAINT "WoPart_pkey" PRIMARY KEY ("id")
+);
+
+-- CreateTable
+CREATE TABLE "_woAssignees" (
+    "A" INTEGER NOT NULL,
+    "B" INTEGER NOT NULL
+);
+
+-- CreateIndex
+CREATE UNIQUE INDEX "_woAssignees_AB_unique" ON "_woAssignees"("A", "B");
+
+-- CreateIndex
+CREATE INDEX "_woAssignees_B_index" ON "_woAssignees"("B");
+
+-- AddForeignKey
+ALTER TABLE "WorkOrder" ADD CONSTRAINT "WorkOrder_company_id_fkey" FOREIGN KEY ("company_id") REFERENCES "Company"("id") ON DELETE RESTRICT ON UPDATE CASCADE;
+
+-- AddForeignKey
+ALTER TABLE "WorkOrder" ADD CONSTRAINT "WorkOrder_mr_id_fkey" FOREIGN KEY ("mr_id") REFERENCES "MaintainanceRequest"("id") ON DELETE SET NULL ON UPDATE CASCADE;
+
+-- AddForeignKey
+ALTER TABLE "WorkOrder" ADD CONSTRAINT "WorkOrder_asset_id_fkey" FOREIGN KEY ("asset_id") REFERENCES "Asset"("id") ON DELETE RESTRICT ON UPDATE CASCADE;
+
+-- AddForeignKey
+ALTER TABLE "WoTask" ADD CONSTRAINT "WoTask_work_order_id_fkey" FOREIGN KEY ("work_order_id") REFERENCES "WorkOrder"("id") ON DELETE CASCADE ON UPDATE CASCADE;
+
+-- AddForeignKey
+ALTER TABLE "WoLabour" ADD CONSTRAINT "WoLabour_work_order_id_fkey" FOREIGN KEY ("work_order_id") REFERENCES "WorkOrder"("id") ON DELETE CASCADE ON UPDATE CASCADE;
+
+-- AddForeignKey
+ALTER TABLE "WoLabour" ADD CONSTRAINT "WoLabour_user_id_fkey" FOREIGN KEY ("user_id") REFERENCES "User"("id") ON DELETE RESTRICT ON UPDATE CASCADE;
+
+-- AddForeignKey
+ALTER TABLE "WoPart" ADD CONSTRAINT "WoPart_work_order_id_fkey" FOREIGN KEY ("work_order_id") REFERENCES "WorkOrder"("id") ON DELETE CASCADE ON UPDATE CASCADE;
+
+-- AddForeignKey
+ALTER TABLE "_woAssignees" ADD CONSTRAINT "_woAssignees_A_fkey" FOREIGN KEY ("A") REFERENCES "User"("id") ON DELETE CASCADE ON UPDATE CASCADE;
+
+-- AddForeignKey
+ALTER TABLE "_woAssignees" ADD CONSTRAINT "_woAssignees_B_fkey" FOREIGN KEY ("B") REFERENCES "WorkOrder"("id") ON DELETE CASCADE ON UPDATE CASCADE;
@@ -0,0 +1,30 @@
+/*
+  Warnings:
+
+  - Roles of existing companies get a privilege on the new WORK_ORDER module: EDIT when they can administrate the company, VIEW when they can see maintenance requests and NONE otherwise.
+  - Kept apart from the migration adding the enum value, Postgres does not allow using a new enum value in the transaction which added it.
+
+*/
+-- MigrateData
+INSERT INTO "Privilege" ("privilege_type", "role_id", "module")
+SELECT CASE
+        WHEN EXISTS (
+            SELECT 1 FROM "Privilege" p
+            WHERE p."role_id" = r."id" AND p."module" = 'ADMIN'::"Module" AND p."privilege_type" = 'EDIT'::"PrivilegeType"
+        ) THEN 'EDIT'::"PrivilegeType"
+        WHEN EXISTS (
+            SELECT 1 FROM "Privilege" p
+            WHERE p."role_id" = r."id" AND p."module" = 'MaintainanceRequest'::"Module" AND p."privilege_type" <> 'NONE'::"PrivilegeType"
+        ) AND NOT EXISTS (
+            SELECT 1 FROM "Privilege" p
+            WHERE p."role_id" = r."id" AND p."module" = 'MaintainanceRequest'::"Module" AND p."privilege_type" = 'NONE'::"PrivilegeType"
+        ) THEN 'VIEW'::"PrivilegeType"
+        ELSE 'NONE'::"PrivilegeType"
+    END,
+    r."id",
+    'WORK_ORDER'::"Module"
+FROM "Role" r
+WHERE NOT EXISTS (
+    SELECT 1 FROM "Privilege" p
+    WHERE p."role_id" = r."id" AND p."module" = 'WORK_ORDER'::"Module"
+);
@@ -0,0 +1,258 @@
+use std::collections::HashMap;
+
+use axum::debug_handler;
+use axum::extract::Path;
+use axum::Json;
+use chrono::{DateTime, FixedOffset};
+use prisma_client_rust::{raw, Direction, PrismaValue, QueryError};
+use serde::{Deserialize, Serialize};
+
+use crate::db::{self, PrismaClient};
+use crate::errors::{AppResult, CommonResponse};
+use crate::utils::JwtClaims;
+use crate::DB;
+
+db::asset_event::select! { asset_event_out {
+    id
+    created_at
+    event_type
+    from_id
+    to_id
+    user: select {
+        id
+        username
+        nickname
+    }
+}}
+
+/// A change that goes into the history of an asset.
+pub struct AssetChange {
+    event_type: db::AssetEventType,
+    from_id: Option<i32>,
+    to_id: Option<i32>,
+}
+
+impl AssetChange {
+    pub fn created() -> Self {
+        Self {
+            event_type: db::AssetEventType::Created,
+            from_id: None,
+            to_id: None,
+        }
+    }
+
+    /// The changes an update makes to the status, location and parent of the
+    /// asset, values that stay the same are left out.
+    pub fn diff(
+        asset: &db::asset::Data,
+        status_id: Option<i32>,
+        location_id: Option<i32>,
+        parent_id: Option<Option<i32>>,
+    ) -> Vec<Self> {
+        let mut changes = vec![];
+        if let Some(id) = status_id.filter(|id| *id != asset.asset_status_id) {
+            changes.push(Self {
+                event_type: db::AssetEventType::StatusChanged,
+                from_id: Some(asset.asset_status_id),
+                to_id: Some(id),
+            });
+        }
+        if let Some(id) = location_id.filter(|id| *id != asset.asset_location_id) {
+            changes.push(Self {
+                event_type: db::AssetEventType::LocationChanged,
+                from_id: Some(asset.asset_location_id),
+                to_id: Some(id),
+            });
+        }
+        if let Some(id) = parent_id.filter(|id| *id != asset.asset_id) {
+            changes.push(Self {
+                event_type: db::AssetEventType::ParentChanged,
+                from_id: asset.asset_id,
+                to_id: id,
+            });
+        }
+        changes
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct LockedAsset {
+    #[allow(dead_code)]
+    id: i32,
+}
+
+/// Read the asset to diff a change against. Meant to run in the transaction
+/// making the change, the row stays locked until it ends so concurrent
+/// changes are recorded one after the other.
+pub async fn lock_asset(
+    client: &PrismaClient,
+    id: i32,
+) -> Result<Option<db::asset::Data>, QueryError> {
+    let _: Vec<LockedAsset> = client
+        ._query_raw(raw!(
+            "SELECT id FROM \"Asset\" WHERE id = {} FOR UPDATE",
+            PrismaValue::Int(id as i64)
+        ))
+        .exec()
+        .await?;
+    client
+        .asset()
+        .find_unique(db::asset::id::equals(id))
+        .exec()
+        .await
+}
+
+/// Append the changes to the history of the asset, meant to run in the same
+/// transaction as the change itself.
+pub async fn record_asset_events(
+    client: &PrismaClient,
+    company_id: i32,
+    user_id: i32,
+    asset_id: i32,
+    changes: Vec<AssetChange>,
+) -> Result<i64, QueryError> {
+    if changes.is_empty() {
+        return Ok(0);
+    }
+    client
+        .asset_event()
+        .create_many(
+            changes
+                .into_iter()
+                .map(|change| {
+                    db::asset_event::create_unchecked(
+                        company_id,
+                        asset_id,
+                        user_id,
+                        change.event_type,
+                        vec![
+                            db::asset_event::from_id::set(change.from_id),
+                            db::asset_event::to_id::set(change.to_id),
+                        ],
+                    )
+                })
+                .collect(),
+        )
+        .exec()
+        .await
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EventTarget {
+    pub id: i32,
+    pub code: String,
+    pub name: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AssetEventOut {
+    pub id: i32,
+    pub created_at: DateTime<FixedOffset>,
+    pub event_type: db::AssetEventType,
+    pub user: asset_event_out::user::Data,
+    /// absent when there was none or it has been purged since
+    pub from: Option<EventTarget>,
+    pub to: Option<EventTarget>,
+}
+
+/// The statuses, locations or assets with the ids, deleted ones included as
+/// the history still refers to them.
+macro_rules! event_targets {
+    ($tenant:expr, $model:ident, $ids:expr, $code:ident, $name:ident) => {{
+        let repo = $tenant.$model();
+        let ids: Vec<i32> = $ids;
+        let mut records = repo
+            .find_many(vec![db::$model::id::in_vec(ids.clone())])
+            .exec()
+            .await?;
+        records.extend(
+            repo.find_deleted(vec![db::$model::id::in_vec(ids)])
+                .exec()
+                .await?,
+        );
+        records
+            .into_iter()
+            .map(|r| {
+                (
+                    r.id,
+                    EventTarget {
+                        id: r.id,
+                        code: r.$code,
+                        name: r.$name,
+                    },
+                )
+            })
+            .collect::<HashMap<_, _>>()
+    }};
+}
+
+/// The history of the asset, oldest first.
+#[debug_handler]
+pub async fn get_asset_history(
+    Path(id): Path<i32>,
+    c: JwtClaims,
+) -> AppResult<Json<CommonResponse<Vec<AssetEventOut>>>> {
+    c.check_module_privilige(db::Module::Asset, db::PrivilegeType::View)
+        .await?;
+    let client = DB.get().unwrap();
+    let tenant = c.tenant(client);
+    tenant.asset().ensure(id).await?;
+    let events = tenant
+        .asset_event()
+        .find_many(vec![db::asset_event::asset_id::equals(id)])
+        .order_by(db::asset_event::id::order(Direction::Asc))
+        .select(asset_event_out::select())
+        .exec()
+        .await?;
+    let ids = |event_type: db::AssetEventType| -> Vec<i32> {
+        events
+            .iter()
+            .filter(|e| e.event_type == event_type)
+            .flat_map(|e| [e.from_id, e.to_id])
+            .flatten()
+            .collect()
+    };
+    let statuses = event_targets!(
+        tenant,
+        asset_status,
+        ids(db::AssetEventType::StatusChanged),
+        status_code,
+        status_name
+    );
+    let locations = event_targets!(
+        tenant,
+        asset_location,
+        ids(db::AssetEventType::LocationChanged),
+        location_code,
+        location_name
+    );
+    let assets = event_targets!(
+        tenant,
+        asset,
+        ids(db::AssetEventType::ParentChanged),
+        asset_code,
+        asset_name
+    );
+    CommonResponse::json_data(
+        events
+            .into_iter()
+            .map(|e| {
+                let targets = match e.event_type {
+                    db::AssetEventType::StatusChanged => Some(&statuses),
+                    db::AssetEventType::LocationChanged => Some(&locations),
+                    db::AssetEventType::ParentChanged => Some(&assets),
+                    db::AssetEventType::Created => None,
+                };
+                let target = |id: Option<i32>| Some(targets?.get(&id?)?.clone());
+                AssetEventOut {
+                    id: e.id,
+                    created_at: e.created_at,
+                    event_type: e.event_type,
+                    user: e.user,
+                    from: target(e.from_id),
+                    to: target(e.to_id),
+                }
+            })
+            .collect(),
+    )
+}
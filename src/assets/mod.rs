@@ -1,13 +1,25 @@
+use crate::custom_field::validate_custom_fields;
 use crate::errors::{AppError, AppResult, CommonResponse};
+use crate::repo::Tenant;
+use crate::repo::{check_references, check_unused, page_size, Page, SortOrder};
 use crate::utils::JwtClaims;
 use crate::DB;
-use crate::{cmp_company_id, db};
+use crate::{db, field_policy};
+
+use std::collections::HashMap;
 
 use axum::debug_handler;
-use axum::extract::Path;
+use axum::extract::{Path, Query};
 use axum::Json;
+use prisma_client_rust::{raw, Direction, PrismaValue};
 use serde::{Deserialize, Serialize};
 
+pub mod history;
+pub use history::get_asset_history;
+use history::{lock_asset, record_asset_events, AssetChange};
+pub mod tree;
+use tree::{asset_tree, location_tree, lock_tree};
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AssetCreateInfo {
     pub asset_code: String,
@@ -15,11 +27,9 @@ pub struct AssetCreateInfo {
     pub asset_description: String,
     pub asset_location_id: i32,
     pub asset_status_id: i32,
-    pub customize_fileds_1: Option<String>,
-    pub customize_fileds_2: Option<String>,
-    pub customize_fileds_3: Option<String>,
-    pub customize_fileds_4: Option<String>,
-    pub customize_fileds_5: Option<String>,
+    /// the parent asset the new asset is a component of
+    pub asset_id: Option<i32>,
+    pub custom_fields: Option<serde_json::Value>,
 }
 
 #[debug_handler]
@@ -30,28 +40,59 @@ pub async fn create_asset(
     c.check_module_privilige(db::Module::Asset, db::PrivilegeType::Edit)
         .await?;
     let client = DB.get().unwrap();
+    let tenant = c.tenant(client);
+    check_references(vec![
+        (
+            "asset_location_id",
+            tenant
+                .asset_location()
+                .owns(payload.asset_location_id)
+                .await?,
+        ),
+        (
+            "asset_status_id",
+            tenant.asset_status().owns(payload.asset_status_id).await?,
+        ),
+        ("asset_id", tenant.asset().owns_opt(payload.asset_id).await?),
+    ])?;
+    let custom_fields = validate_custom_fields(
+        tenant,
+        db::CustomFieldEntity::Asset,
+        payload.custom_fields,
+        None,
+    )
+    .await?;
+    let (company_id, user_id) = (c.company_id, c.user_id);
     let a = client
         ._transaction()
         .run(|client| async move {
-            client
+            let a = Tenant::new(&client, company_id)
                 .asset()
-                .create(
-                    db::company::id::equals(c.company_id),
-                    payload.asset_code,
-                    payload.asset_name,
-                    payload.asset_description,
-                    db::asset_location::id::equals(payload.asset_location_id),
-                    db::asset_status::id::equals(payload.asset_status_id),
-                    vec![
-                        db::asset::customize_fileds_1::set(payload.customize_fileds_1),
-                        db::asset::customize_fileds_2::set(payload.customize_fileds_2),
-                        db::asset::customize_fileds_3::set(payload.customize_fileds_3),
-                        db::asset::customize_fileds_4::set(payload.customize_fileds_4),
-                        db::asset::customize_fileds_5::set(payload.customize_fileds_5),
-                    ],
-                )
+                .create(|assets, company| {
+                    assets.create(
+                        company,
+                        payload.asset_code,
+                        payload.asset_name,
+                        payload.asset_description,
+                        db::asset_location::id::equals(payload.asset_location_id),
+                        db::asset_status::id::equals(payload.asset_status_id),
+                        vec![
+                            db::asset::asset_id::set(payload.asset_id),
+                            db::asset::custom_fields::set(custom_fields),
+                        ],
+                    )
+                })
                 .exec()
-                .await
+                .await?;
+            record_asset_events(
+                &client,
+                company_id,
+                user_id,
+                a.id,
+                vec![AssetChange::created()],
+            )
+            .await?;
+            Ok::<_, AppError>(a)
         })
         .await?;
     CommonResponse::json_data(a)
@@ -76,7 +117,8 @@ db::asset::select! {
             status_name
         }
         parent_asset
-        children_asset
+        children_asset(vec![db::asset::deleted_at::equals(None)])
+        custom_fields
     }
 }
 
@@ -88,16 +130,15 @@ pub async fn get_asset(
     c.check_module_privilige(db::Module::Asset, db::PrivilegeType::View)
         .await?;
     let client = DB.get().unwrap();
-    let a = client
+    let a = c
+        .tenant(client)
         .asset()
-        .find_unique(db::asset::id::equals(id))
+        .find_by_id(id)
         .select(asset_out::select())
         .exec()
         .await?;
     match a {
-        Some(asset) => {
-            cmp_company_id!(asset, c)
-        }
+        Some(asset) => CommonResponse::json_data(asset),
         _ => Err(AppError::Custom {
             status_code: 404,
             error: "asset not found".to_string(),
@@ -105,6 +146,190 @@ pub async fn get_asset(
     }
 }
 
+#[debug_handler]
+pub async fn get_asset_by_code(
+    Path(code): Path<String>,
+    c: JwtClaims,
+) -> AppResult<Json<CommonResponse<asset_out::Data>>> {
+    c.check_module_privilige(db::Module::Asset, db::PrivilegeType::View)
+        .await?;
+    let client = DB.get().unwrap();
+    let a = c
+        .tenant(client)
+        .asset()
+        .find_first(vec![db::asset::asset_code::equals(code)])
+        .select(asset_out::select())
+        .exec()
+        .await?;
+    match a {
+        Some(asset) => CommonResponse::json_data(asset),
+        _ => Err(AppError::Custom {
+            status_code: 404,
+            error: "asset not found".to_string(),
+        }),
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TreeQuery {
+    /// the whole tree of the company when absent
+    pub root_id: Option<i32>,
+    /// the number of levels below the root to include, unlimited when absent
+    pub depth: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AssetSort {
+    #[default]
+    AssetCode,
+    AssetName,
+    CreatedAt,
+    UpdatedAt,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AssetListQuery {
+    pub cursor: Option<i32>,
+    pub limit: Option<i64>,
+    #[serde(default)]
+    pub sort: AssetSort,
+    #[serde(default)]
+    pub order: SortOrder,
+    /// matches assets in the location and all of its descendants
+    pub location_id: Option<i32>,
+    pub status_id: Option<i32>,
+    pub parent_id: Option<i32>,
+    /// prefix of the asset code
+    pub code: Option<String>,
+    /// prefix of the asset name
+    pub name: Option<String>,
+    /// a JSON object the custom fields of an asset must contain
+    pub custom_fields: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AssetId {
+    id: i32,
+}
+
+/// Ids of the company's assets whose custom fields contain `values`.
+async fn assets_with_custom_fields(
+    client: &db::PrismaClient,
+    company_id: i32,
+    values: &str,
+) -> AppResult<Vec<i32>> {
+    match serde_json::from_str::<serde_json::Value>(values) {
+        Ok(serde_json::Value::Object(_)) => {}
+        _ => return Err(AppError::InvalidFields(vec!["custom_fields".to_string()])),
+    }
+    let rows: Vec<AssetId> = client
+        ._query_raw(raw!(
+            "SELECT id FROM \"Asset\" WHERE company_id = {} AND deleted_at IS NULL AND custom_fields @> {}::jsonb",
+            PrismaValue::Int(company_id as i64),
+            PrismaValue::String(values.to_string())
+        ))
+        .exec()
+        .await?;
+    Ok(rows.into_iter().map(|r| r.id).collect())
+}
+
+/// An asset listing query with the location subtree and the custom field
+/// matches resolved, shared by the listing and the export.
+pub struct AssetFilter {
+    pub query: AssetListQuery,
+    locations: Option<Vec<i32>>,
+    matching: Option<Vec<i32>>,
+}
+
+impl AssetFilter {
+    pub async fn resolve(
+        client: &db::PrismaClient,
+        company_id: i32,
+        query: AssetListQuery,
+    ) -> AppResult<Self> {
+        let locations = match query.location_id {
+            Some(id) => Some(
+                location_tree(Tenant::new(client, company_id))
+                    .await?
+                    .descendants(id),
+            ),
+            None => None,
+        };
+        let matching = match &query.custom_fields {
+            Some(values) => Some(assets_with_custom_fields(client, company_id, values).await?),
+            None => None,
+        };
+        Ok(Self {
+            query,
+            locations,
+            matching,
+        })
+    }
+
+    pub fn params(&self) -> Vec<db::asset::WhereParam> {
+        let q = &self.query;
+        let mut filters = vec![];
+        if let Some(ids) = &self.locations {
+            filters.push(db::asset::asset_location_id::in_vec(ids.clone()));
+        }
+        if let Some(status_id) = q.status_id {
+            filters.push(db::asset::asset_status_id::equals(status_id));
+        }
+        if let Some(parent_id) = q.parent_id {
+            filters.push(db::asset::asset_id::equals(Some(parent_id)));
+        }
+        if let Some(code) = &q.code {
+            filters.push(db::asset::asset_code::starts_with(code.clone()));
+        }
+        if let Some(name) = &q.name {
+            filters.push(db::asset::asset_name::starts_with(name.clone()));
+        }
+        if let Some(ids) = &self.matching {
+            filters.push(db::asset::id::in_vec(ids.clone()));
+        }
+        filters
+    }
+
+    /// The requested sort, the id breaks ties so that cursors are stable.
+    pub fn order_by(&self) -> Vec<db::asset::OrderByParam> {
+        let direction = Direction::from(self.query.order);
+        vec![
+            match self.query.sort {
+                AssetSort::AssetCode => db::asset::asset_code::order(direction),
+                AssetSort::AssetName => db::asset::asset_name::order(direction),
+                AssetSort::CreatedAt => db::asset::created_at::order(direction),
+                AssetSort::UpdatedAt => db::asset::updated_at::order(direction),
+            },
+            db::asset::id::order(direction),
+        ]
+    }
+}
+
+#[debug_handler]
+pub async fn list_assets(
+    c: JwtClaims,
+    Query(q): Query<AssetListQuery>,
+) -> AppResult<Json<CommonResponse<Page<asset_out::Data>>>> {
+    c.check_module_privilige(db::Module::Asset, db::PrivilegeType::View)
+        .await?;
+    let client = DB.get().unwrap();
+    let tenant = c.tenant(client);
+    let size = page_size(q.limit);
+    let cursor = q.cursor;
+    let filter = AssetFilter::resolve(client, c.company_id, q).await?;
+    let total = tenant.asset().count(filter.params()).exec().await?;
+    let mut query = tenant.asset().find_many(filter.params()).take(size + 1);
+    for order in filter.order_by() {
+        query = query.order_by(order);
+    }
+    if let Some(cursor) = cursor {
+        query = query.cursor(db::asset::id::equals(cursor)).skip(1);
+    }
+    let assets = query.select(asset_out::select()).exec().await?;
+    CommonResponse::json_data(Page::new(assets, total, size, |a| a.id))
+}
+
 db::asset::partial!(
     UpdateAssetInfo {
         asset_name
@@ -112,33 +337,220 @@ db::asset::partial!(
         asset_location_id
         asset_status_id
         asset_id
-        customize_fileds_1
-        customize_fileds_2
-        customize_fileds_3
-        customize_fileds_4
-        customize_fileds_5
+        custom_fields
     }
 );
 
+field_policy!(UpdateAssetInfo {
+    asset_location_id => (Location, View),
+});
+
 #[debug_handler]
 pub async fn update_asset(
     Path(id): Path<i32>,
     c: JwtClaims,
-    Json(payload): Json<UpdateAssetInfo>,
+    Json(mut payload): Json<UpdateAssetInfo>,
 ) -> AppResult<Json<CommonResponse<asset_out::Data>>> {
     c.check_module_privilige(db::Module::Asset, db::PrivilegeType::Edit)
         .await?;
+    c.check_field_privileges(&payload).await?;
     let client = DB.get().unwrap();
-    CommonResponse::json_data(
-        client
-            .asset()
-            .update(db::asset::id::equals(id), payload.to_params())
-            .select(asset_out::select())
-            .exec()
+    let tenant = c.tenant(client);
+    check_references(vec![
+        (
+            "asset_location_id",
+            tenant
+                .asset_location()
+                .owns_opt(payload.asset_location_id)
+                .await?,
+        ),
+        (
+            "asset_status_id",
+            tenant
+                .asset_status()
+                .owns_opt(payload.asset_status_id)
+                .await?,
+        ),
+        (
+            "asset_id",
+            tenant.asset().owns_opt(payload.asset_id.flatten()).await?,
+        ),
+    ])?;
+    let asset = tenant.asset().get(id).await?;
+    let mut params = vec![];
+    if let Some(values) = payload.custom_fields.take() {
+        params.push(db::asset::custom_fields::set(
+            validate_custom_fields(
+                tenant,
+                db::CustomFieldEntity::Asset,
+                Some(values),
+                Some(&asset.custom_fields),
+            )
             .await?,
+        ));
+    }
+    let (status_id, location_id, parent_id) = (
+        payload.asset_status_id,
+        payload.asset_location_id,
+        payload.asset_id,
+    );
+    params.extend(payload.to_params());
+    let (company_id, user_id) = (c.company_id, c.user_id);
+    let a = client
+        ._transaction()
+        .run(|client| async move {
+            if let Some(parent_id) = parent_id {
+                lock_tree(&client, company_id).await?;
+                asset_tree(Tenant::new(&client, company_id))
+                    .await?
+                    .check_parent(id, parent_id)?;
+            }
+            if let Some(asset) = lock_asset(&client, id).await? {
+                let changes = AssetChange::diff(&asset, status_id, location_id, parent_id);
+                record_asset_events(&client, company_id, user_id, id, changes).await?;
+            }
+            Ok::<_, AppError>(
+                client
+                    .asset()
+                    .update(db::asset::id::equals(id), params)
+                    .select(asset_out::select())
+                    .exec()
+                    .await?,
+            )
+        })
+        .await?;
+    CommonResponse::json_data(a)
+}
+
+db::asset::select! {
+    asset_crumb {
+        id
+        asset_code
+        asset_name
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AssetNode {
+    pub id: i32,
+    pub asset_code: String,
+    pub asset_name: String,
+    pub children: Vec<AssetNode>,
+}
+
+#[debug_handler]
+pub async fn get_asset_tree(
+    c: JwtClaims,
+    Query(q): Query<TreeQuery>,
+) -> AppResult<Json<CommonResponse<Vec<AssetNode>>>> {
+    c.check_module_privilige(db::Module::Asset, db::PrivilegeType::View)
+        .await?;
+    let client = DB.get().unwrap();
+    let tenant = c.tenant(client);
+    if let Some(id) = q.root_id {
+        tenant.asset().ensure(id).await?;
+    }
+    let tree = asset_tree(tenant).await?;
+    let assets: HashMap<i32, asset_crumb::Data> = tenant
+        .asset()
+        .find_many(vec![])
+        .select(asset_crumb::select())
+        .exec()
+        .await?
+        .into_iter()
+        .map(|a| (a.id, a))
+        .collect();
+    let roots = q.root_id.map(|id| vec![id]).unwrap_or_else(|| tree.roots());
+    CommonResponse::json_data(tree.nest(&roots, q.depth, &|id, children| {
+        let asset = assets.get(&id)?;
+        Some(AssetNode {
+            id,
+            asset_code: asset.asset_code.clone(),
+            asset_name: asset.asset_name.clone(),
+            children,
+        })
+    }))
+}
+
+/// Every asset the component is built into, the top level asset first and
+/// the direct parent last.
+#[debug_handler]
+pub async fn get_asset_where_used(
+    Path(id): Path<i32>,
+    c: JwtClaims,
+) -> AppResult<Json<CommonResponse<Vec<asset_crumb::Data>>>> {
+    c.check_module_privilige(db::Module::Asset, db::PrivilegeType::View)
+        .await?;
+    let client = DB.get().unwrap();
+    let tenant = c.tenant(client);
+    tenant.asset().ensure(id).await?;
+    let ancestors = asset_tree(tenant).await?.ancestors(id);
+    let mut assets: HashMap<i32, asset_crumb::Data> = tenant
+        .asset()
+        .find_many(vec![db::asset::id::in_vec(ancestors.clone())])
+        .select(asset_crumb::select())
+        .exec()
+        .await?
+        .into_iter()
+        .map(|a| (a.id, a))
+        .collect();
+    CommonResponse::json_data(
+        ancestors
+            .iter()
+            .filter_map(|id| assets.remove(id))
+            .collect(),
     )
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MoveAssetInfo {
+    /// the new parent, the asset becomes a top level asset when absent
+    pub parent_id: Option<i32>,
+}
+
+#[debug_handler]
+pub async fn move_asset(
+    Path(id): Path<i32>,
+    c: JwtClaims,
+    Json(payload): Json<MoveAssetInfo>,
+) -> AppResult<Json<CommonResponse<asset_out::Data>>> {
+    c.check_module_privilige(db::Module::Asset, db::PrivilegeType::Edit)
+        .await?;
+    let client = DB.get().unwrap();
+    let tenant = c.tenant(client);
+    tenant.asset().ensure(id).await?;
+    check_references(vec![(
+        "parent_id",
+        tenant.asset().owns_opt(payload.parent_id).await?,
+    )])?;
+    let (company_id, user_id) = (c.company_id, c.user_id);
+    let a = client
+        ._transaction()
+        .run(|client| async move {
+            lock_tree(&client, company_id).await?;
+            asset_tree(Tenant::new(&client, company_id))
+                .await?
+                .check_parent(id, payload.parent_id)?;
+            if let Some(asset) = lock_asset(&client, id).await? {
+                let changes = AssetChange::diff(&asset, None, None, Some(payload.parent_id));
+                record_asset_events(&client, company_id, user_id, id, changes).await?;
+            }
+            Ok::<_, AppError>(
+                client
+                    .asset()
+                    .update(
+                        db::asset::id::equals(id),
+                        vec![db::asset::asset_id::set(payload.parent_id)],
+                    )
+                    .select(asset_out::select())
+                    .exec()
+                    .await?,
+            )
+        })
+        .await?;
+    CommonResponse::json_data(a)
+}
+
 #[debug_handler]
 pub async fn delete_asset(
     Path(id): Path<i32>,
@@ -147,10 +559,24 @@ pub async fn delete_asset(
     c.check_module_privilige(db::Module::Asset, db::PrivilegeType::Edit)
         .await?;
     let client = DB.get().unwrap();
+    let tenant = c.tenant(client);
+    tenant.asset().ensure(id).await?;
+    check_unused(
+        "asset",
+        vec![(
+            "assets",
+            tenant
+                .asset()
+                .count(vec![db::asset::asset_id::equals(Some(id))])
+                .exec()
+                .await?,
+        )],
+    )?;
     CommonResponse::json_data(
-        client
+        tenant
             .asset()
-            .delete(db::asset::id::equals(id))
+            .soft_delete(id)
+            .await?
             .select(asset_out::select())
             .exec()
             .await?,
@@ -173,16 +599,26 @@ pub async fn create_location(
     c.check_module_privilige(db::Module::Location, db::PrivilegeType::Edit)
         .await?;
     let client = DB.get().unwrap();
-    let l = client
+    check_references(vec![(
+        "parent_id",
+        c.tenant(client)
+            .asset_location()
+            .owns_opt(payload.parent_id)
+            .await?,
+    )])?;
+    let l = c
+        .tenant(client)
         .asset_location()
-        .create(
-            db::company::id::equals(c.company_id),
-            payload.location_code,
-            payload.location_name,
-            payload.location_description,
-            // vec![],
-            vec![db::asset_location::parent_id::set(payload.parent_id)],
-        )
+        .create(|locations, company| {
+            locations.create(
+                company,
+                payload.location_code,
+                payload.location_name,
+                payload.location_description,
+                // vec![],
+                vec![db::asset_location::parent_id::set(payload.parent_id)],
+            )
+        })
         .exec()
         .await?;
     CommonResponse::json_data(l)
@@ -198,22 +634,86 @@ db::asset_location::select! {
     }
 }
 
+db::asset_location::select! {
+    location_crumb {
+        id
+        location_code
+        location_name
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LocationDetails {
+    #[serde(flatten)]
+    pub location: location_out::Data,
+    /// the ancestors of the location, the root first
+    pub path: Vec<location_crumb::Data>,
+}
+
+async fn location_details(
+    tenant: Tenant<'_>,
+    location: location_out::Data,
+) -> AppResult<LocationDetails> {
+    let ancestors = location_tree(tenant).await?.ancestors(location.id);
+    let mut crumbs: HashMap<i32, location_crumb::Data> = tenant
+        .asset_location()
+        .find_many(vec![db::asset_location::id::in_vec(ancestors.clone())])
+        .select(location_crumb::select())
+        .exec()
+        .await?
+        .into_iter()
+        .map(|l| (l.id, l))
+        .collect();
+    Ok(LocationDetails {
+        location,
+        path: ancestors
+            .iter()
+            .filter_map(|id| crumbs.remove(id))
+            .collect(),
+    })
+}
+
 #[debug_handler]
 pub async fn get_location(
     Path(id): Path<i32>,
     c: JwtClaims,
-) -> AppResult<Json<CommonResponse<location_out::Data>>> {
+) -> AppResult<Json<CommonResponse<LocationDetails>>> {
     c.check_module_privilige(db::Module::Location, db::PrivilegeType::View)
         .await?;
     let client = DB.get().unwrap();
-    let l = client
+    let tenant = c.tenant(client);
+    let l = tenant
         .asset_location()
-        .find_unique(db::asset_location::id::equals(id))
+        .find_by_id(id)
         .select(location_out::select())
         .exec()
         .await?;
     match l {
-        Some(location) => cmp_company_id!(location, c),
+        Some(location) => CommonResponse::json_data(location_details(tenant, location).await?),
+        _ => Err(AppError::Custom {
+            status_code: 404,
+            error: "location not found".to_string(),
+        }),
+    }
+}
+
+#[debug_handler]
+pub async fn get_location_by_code(
+    Path(code): Path<String>,
+    c: JwtClaims,
+) -> AppResult<Json<CommonResponse<LocationDetails>>> {
+    c.check_module_privilige(db::Module::Location, db::PrivilegeType::View)
+        .await?;
+    let client = DB.get().unwrap();
+    let tenant = c.tenant(client);
+    let l = tenant
+        .asset_location()
+        .find_first(vec![db::asset_location::location_code::equals(code)])
+        .select(location_out::select())
+        .exec()
+        .await?;
+    match l {
+        Some(location) => CommonResponse::json_data(location_details(tenant, location).await?),
         _ => Err(AppError::Custom {
             status_code: 404,
             error: "location not found".to_string(),
@@ -228,7 +728,7 @@ db::asset_location::select! {
         location_name
         location_description
         parent_loaction
-        children_location
+        children_location(vec![db::asset_location::deleted_at::equals(None)])
     }
 }
 
@@ -240,15 +740,142 @@ pub async fn get_nested_location(
         .await?;
     let client = DB.get().unwrap();
     CommonResponse::json_data(
-        client
+        c.tenant(client)
             .asset_location()
-            .find_many(vec![db::asset_location::company_id::equals(c.company_id)])
+            .find_many(vec![])
             .select(location_nested_out::select())
             .exec()
             .await?,
     )
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LocationNode {
+    pub id: i32,
+    pub location_code: String,
+    pub location_name: String,
+    /// assets directly in the location
+    pub asset_count: i32,
+    /// assets in the location and all of its descendants
+    pub total_asset_count: i32,
+    pub children: Vec<LocationNode>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LocationAssetCount {
+    asset_location_id: i32,
+    count: i32,
+}
+
+async fn location_asset_counts(
+    client: &db::PrismaClient,
+    company_id: i32,
+) -> AppResult<HashMap<i32, i32>> {
+    let rows: Vec<LocationAssetCount> = client
+        ._query_raw(raw!(
+            "SELECT asset_location_id, COUNT(*)::int AS count FROM \"Asset\" WHERE company_id = {} AND deleted_at IS NULL GROUP BY asset_location_id",
+            PrismaValue::Int(company_id as i64)
+        ))
+        .exec()
+        .await?;
+    Ok(rows
+        .into_iter()
+        .map(|r| (r.asset_location_id, r.count))
+        .collect())
+}
+
+#[debug_handler]
+pub async fn get_location_tree(
+    c: JwtClaims,
+    Query(q): Query<TreeQuery>,
+) -> AppResult<Json<CommonResponse<Vec<LocationNode>>>> {
+    c.check_module_privilige(db::Module::Location, db::PrivilegeType::View)
+        .await?;
+    let client = DB.get().unwrap();
+    let tenant = c.tenant(client);
+    if let Some(id) = q.root_id {
+        tenant.asset_location().ensure(id).await?;
+    }
+    let tree = location_tree(tenant).await?;
+    let locations: HashMap<i32, location_crumb::Data> = tenant
+        .asset_location()
+        .find_many(vec![])
+        .select(location_crumb::select())
+        .exec()
+        .await?
+        .into_iter()
+        .map(|l| (l.id, l))
+        .collect();
+    let counts = location_asset_counts(client, c.company_id).await?;
+    let totals = tree.subtree_totals(&counts);
+    let roots = q.root_id.map(|id| vec![id]).unwrap_or_else(|| tree.roots());
+    CommonResponse::json_data(tree.nest(&roots, q.depth, &|id, children| {
+        let location = locations.get(&id)?;
+        Some(LocationNode {
+            id,
+            location_code: location.location_code.clone(),
+            location_name: location.location_name.clone(),
+            asset_count: counts.get(&id).copied().unwrap_or(0),
+            total_asset_count: totals.get(&id).copied().unwrap_or(0),
+            children,
+        })
+    }))
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MoveLocationInfo {
+    /// the new parent, the location becomes a root when absent
+    pub parent_id: Option<i32>,
+}
+
+/// Fails unless the new parent is a location of the company outside of the
+/// subtree of the moved location.
+async fn check_location_parent(
+    tenant: Tenant<'_>,
+    id: i32,
+    parent_id: Option<i32>,
+) -> AppResult<()> {
+    check_references(vec![(
+        "parent_id",
+        tenant.asset_location().owns_opt(parent_id).await?,
+    )])?;
+    location_tree(tenant).await?.check_parent(id, parent_id)
+}
+
+#[debug_handler]
+pub async fn move_location(
+    Path(id): Path<i32>,
+    c: JwtClaims,
+    Json(payload): Json<MoveLocationInfo>,
+) -> AppResult<Json<CommonResponse<LocationDetails>>> {
+    c.check_module_privilige(db::Module::Location, db::PrivilegeType::Edit)
+        .await?;
+    let client = DB.get().unwrap();
+    let company_id = c.company_id;
+    let l = client
+        ._transaction()
+        .run(|client| async move {
+            lock_tree(&client, company_id).await?;
+            let tenant = Tenant::new(&client, company_id);
+            tenant.asset_location().ensure(id).await?;
+            check_location_parent(tenant, id, payload.parent_id).await?;
+            Ok::<_, AppError>(
+                tenant
+                    .asset_location()
+                    .update(
+                        id,
+                        vec![db::asset_location::parent_id::set(payload.parent_id)],
+                    )
+                    .await?
+                    .select(location_out::select())
+                    .exec()
+                    .await?,
+            )
+        })
+        .await?;
+    CommonResponse::json_data(location_details(c.tenant(client), l).await?)
+}
+
 db::asset_location::partial!(
     UpdateLocationInfo {
         location_name
@@ -266,14 +893,28 @@ pub async fn update_location(
     c.check_module_privilige(db::Module::Location, db::PrivilegeType::Edit)
         .await?;
     let client = DB.get().unwrap();
-    CommonResponse::json_data(
-        client
-            .asset_location()
-            .update(db::asset_location::id::equals(id), payload.to_params())
-            .select(location_out::select())
-            .exec()
-            .await?,
-    )
+    let company_id = c.company_id;
+    let l = client
+        ._transaction()
+        .run(|client| async move {
+            let tenant = Tenant::new(&client, company_id);
+            if let Some(parent_id) = payload.parent_id {
+                lock_tree(&client, company_id).await?;
+                tenant.asset_location().ensure(id).await?;
+                check_location_parent(tenant, id, parent_id).await?;
+            }
+            Ok::<_, AppError>(
+                tenant
+                    .asset_location()
+                    .update(id, payload.to_params())
+                    .await?
+                    .select(location_out::select())
+                    .exec()
+                    .await?,
+            )
+        })
+        .await?;
+    CommonResponse::json_data(l)
 }
 
 #[debug_handler]
@@ -284,10 +925,34 @@ pub async fn delete_location(
     c.check_module_privilige(db::Module::Location, db::PrivilegeType::Edit)
         .await?;
     let client = DB.get().unwrap();
+    let tenant = c.tenant(client);
+    tenant.asset_location().ensure(id).await?;
+    check_unused(
+        "location",
+        vec![
+            (
+                "assets",
+                tenant
+                    .asset()
+                    .count(vec![db::asset::asset_location_id::equals(id)])
+                    .exec()
+                    .await?,
+            ),
+            (
+                "locations",
+                tenant
+                    .asset_location()
+                    .count(vec![db::asset_location::parent_id::equals(Some(id))])
+                    .exec()
+                    .await?,
+            ),
+        ],
+    )?;
     CommonResponse::json_data(
-        client
+        tenant
             .asset_location()
-            .delete(db::asset_location::id::equals(id))
+            .soft_delete(id)
+            .await?
             .select(location_out::select())
             .exec()
             .await?,
@@ -308,14 +973,12 @@ pub async fn create_status(
     c.check_module_privilige(db::Module::Asset, db::PrivilegeType::Edit)
         .await?;
     let client = DB.get().unwrap();
-    let s = client
+    let s = c
+        .tenant(client)
         .asset_status()
-        .create(
-            db::company::id::equals(c.company_id),
-            payload.status_code,
-            payload.status_name,
-            vec![],
-        )
+        .create(|statuses, company| {
+            statuses.create(company, payload.status_code, payload.status_name, vec![])
+        })
         .exec()
         .await?;
     CommonResponse::json_data(s)
@@ -329,13 +992,37 @@ pub async fn get_status(
     c.check_module_privilige(db::Module::Asset, db::PrivilegeType::View)
         .await?;
     let client = DB.get().unwrap();
-    let a = client
+    let a = c
+        .tenant(client)
+        .asset_status()
+        .find_by_id(id)
+        .exec()
+        .await?;
+    match a {
+        Some(s) => CommonResponse::json_data(s),
+        _ => Err(AppError::Custom {
+            status_code: 404,
+            error: "status not found".to_string(),
+        }),
+    }
+}
+
+#[debug_handler]
+pub async fn get_status_by_code(
+    Path(code): Path<String>,
+    c: JwtClaims,
+) -> AppResult<Json<CommonResponse<db::asset_status::Data>>> {
+    c.check_module_privilige(db::Module::Asset, db::PrivilegeType::View)
+        .await?;
+    let client = DB.get().unwrap();
+    let a = c
+        .tenant(client)
         .asset_status()
-        .find_unique(db::asset_status::id::equals(id))
+        .find_first(vec![db::asset_status::status_code::equals(code)])
         .exec()
         .await?;
     match a {
-        Some(s) => cmp_company_id!(s, c),
+        Some(s) => CommonResponse::json_data(s),
         _ => Err(AppError::Custom {
             status_code: 404,
             error: "status not found".to_string(),
@@ -360,9 +1047,10 @@ pub async fn update_asset_status(
         .await?;
     let client = DB.get().unwrap();
     CommonResponse::json_data(
-        client
+        c.tenant(client)
             .asset_status()
-            .update(db::asset_status::id::equals(id), payload.to_params())
+            .update(id, payload.to_params())
+            .await?
             .exec()
             .await?,
     )
@@ -376,11 +1064,18 @@ pub async fn delete_asset_status(
     c.check_module_privilige(db::Module::Asset, db::PrivilegeType::Edit)
         .await?;
     let client = DB.get().unwrap();
-    CommonResponse::json_data(
-        client
-            .asset_status()
-            .delete(db::asset_status::id::equals(id))
-            .exec()
-            .await?,
-    )
+    let tenant = c.tenant(client);
+    tenant.asset_status().ensure(id).await?;
+    check_unused(
+        "status",
+        vec![(
+            "assets",
+            tenant
+                .asset()
+                .count(vec![db::asset::asset_status_id::equals(id)])
+                .exec()
+                .await?,
+        )],
+    )?;
+    CommonResponse::json_data(tenant.asset_status().soft_delete(id).await?.exec().await?)
 }
@@ -0,0 +1,311 @@
+use std::collections::{HashMap, HashSet};
+
+use prisma_client_rust::{raw, PrismaValue, QueryError};
+use serde::Deserialize;
+
+use crate::{
+    db::{self, PrismaClient},
+    errors::{AppError, AppResult},
+    repo::Tenant,
+};
+
+db::asset_location::select! { location_link {
+    id
+    parent_id
+}}
+
+db::asset::select! { asset_link {
+    id
+    asset_id
+}}
+
+/// Parent and child links of every record of a self referencing model of the
+/// company, so that a hierarchy can be walked without a query per level.
+pub struct Tree {
+    parents: HashMap<i32, Option<i32>>,
+    children: HashMap<i32, Vec<i32>>,
+}
+
+impl Tree {
+    pub fn new(links: impl IntoIterator<Item = (i32, Option<i32>)>) -> Self {
+        let mut parents = HashMap::new();
+        let mut children: HashMap<i32, Vec<i32>> = HashMap::new();
+        for (id, parent) in links {
+            parents.insert(id, parent);
+            if let Some(parent) = parent {
+                children.entry(parent).or_default().push(id);
+            }
+        }
+        for ids in children.values_mut() {
+            ids.sort_unstable();
+        }
+        Self { parents, children }
+    }
+
+    /// Records without a parent, sorted by id.
+    pub fn roots(&self) -> Vec<i32> {
+        let mut roots: Vec<i32> = self
+            .parents
+            .iter()
+            .filter(|(_, parent)| match parent {
+                Some(p) => !self.parents.contains_key(p),
+                None => true,
+            })
+            .map(|(id, _)| *id)
+            .collect();
+        roots.sort_unstable();
+        roots
+    }
+
+    pub fn children(&self, id: i32) -> &[i32] {
+        self.children.get(&id).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    /// The id itself followed by the ids of all its descendants, a cycle left
+    /// over in existing data is walked only once.
+    pub fn descendants(&self, id: i32) -> Vec<i32> {
+        let mut seen = HashSet::from([id]);
+        let mut ids = vec![id];
+        let mut i = 0;
+        while i < ids.len() {
+            for child in self.children(ids[i]) {
+                if seen.insert(*child) {
+                    ids.push(*child);
+                }
+            }
+            i += 1;
+        }
+        ids
+    }
+
+    /// The ancestors of the id, the root first and the direct parent last.
+    pub fn ancestors(&self, id: i32) -> Vec<i32> {
+        let mut seen = HashSet::from([id]);
+        let mut ids = vec![];
+        let mut current = self.parents.get(&id).copied().flatten();
+        while let Some(parent) = current {
+            if !seen.insert(parent) {
+                break;
+            }
+            ids.push(parent);
+            current = self.parents.get(&parent).copied().flatten();
+        }
+        ids.reverse();
+        ids
+    }
+
+    /// The sum of `values` over every record and all of its descendants, in a
+    /// single post-order pass. A cycle left over in existing data is walked
+    /// only once, the link closing it is ignored.
+    pub fn subtree_totals(&self, values: &HashMap<i32, i32>) -> HashMap<i32, i32> {
+        let mut totals: HashMap<i32, i32> = HashMap::with_capacity(self.parents.len());
+        let mut seen = HashSet::new();
+        // records of a cycle without any root are only reachable on their own
+        let mut rest: Vec<i32> = self.parents.keys().copied().collect();
+        rest.sort_unstable();
+        for start in self.roots().into_iter().chain(rest) {
+            if !seen.insert(start) {
+                continue;
+            }
+            // the flag tells whether the children have been pushed already
+            let mut stack = vec![(start, false)];
+            while let Some((id, expanded)) = stack.pop() {
+                if expanded {
+                    let total = values.get(&id).copied().unwrap_or(0)
+                        + self
+                            .children(id)
+                            .iter()
+                            .filter_map(|child| totals.get(child))
+                            .sum::<i32>();
+                    totals.insert(id, total);
+                } else {
+                    stack.push((id, true));
+                    for child in self.children(id) {
+                        if seen.insert(*child) {
+                            stack.push((*child, false));
+                        }
+                    }
+                }
+            }
+        }
+        totals
+    }
+
+    /// Nest the records below each of the `roots` down to `depth` levels,
+    /// `node` builds the output of a record from its already nested children.
+    pub fn nest<N>(
+        &self,
+        roots: &[i32],
+        depth: Option<u32>,
+        node: &impl Fn(i32, Vec<N>) -> Option<N>,
+    ) -> Vec<N> {
+        let mut seen = HashSet::new();
+        roots
+            .iter()
+            .filter_map(|id| self.nest_node(*id, depth, node, &mut seen))
+            .collect()
+    }
+
+    fn nest_node<N>(
+        &self,
+        id: i32,
+        depth: Option<u32>,
+        node: &impl Fn(i32, Vec<N>) -> Option<N>,
+        seen: &mut HashSet<i32>,
+    ) -> Option<N> {
+        if !seen.insert(id) {
+            return None;
+        }
+        let children = match depth {
+            Some(0) => vec![],
+            _ => self
+                .children(id)
+                .iter()
+                .filter_map(|child| self.nest_node(*child, depth.map(|d| d - 1), node, seen))
+                .collect(),
+        };
+        node(id, children)
+    }
+
+    /// Fails with a 422 when `parent` is the record itself or one of its
+    /// descendants, as the move would create a cycle.
+    pub fn check_parent(&self, id: i32, parent: Option<i32>) -> AppResult<()> {
+        match parent {
+            Some(parent) if self.descendants(id).contains(&parent) => Err(AppError::Custom {
+                status_code: 422,
+                error: "a record can not be moved below itself or its descendants".to_string(),
+            }),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct LockedCompany {
+    #[allow(dead_code)]
+    id: i32,
+}
+
+/// Make the moves within the hierarchies of the company take turns. Meant to
+/// run in the transaction making the move, before the tree to check it against
+/// is loaded. Locking the moved records alone is not enough, two moves of
+/// different records checked against the same tree can close a cycle.
+pub async fn lock_tree(client: &PrismaClient, company_id: i32) -> Result<(), QueryError> {
+    let _: Vec<LockedCompany> = client
+        ._query_raw(raw!(
+            "SELECT id FROM \"Company\" WHERE id = {} FOR NO KEY UPDATE",
+            PrismaValue::Int(company_id as i64)
+        ))
+        .exec()
+        .await?;
+    Ok(())
+}
+
+pub async fn location_tree(tenant: Tenant<'_>) -> AppResult<Tree> {
+    let links = tenant
+        .asset_location()
+        .find_many(vec![])
+        .select(location_link::select())
+        .exec()
+        .await?;
+    Ok(Tree::new(links.into_iter().map(|l| (l.id, l.parent_id))))
+}
+
+pub async fn asset_tree(tenant: Tenant<'_>) -> AppResult<Tree> {
+    let links = tenant
+        .asset()
+        .find_many(vec![])
+        .select(asset_link::select())
+        .exec()
+        .await?;
+    Ok(Tree::new(links.into_iter().map(|a| (a.id, a.asset_id))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 1 -> 2 -> 3, 1 -> 4 and a cycle 5 -> 6 -> 7 -> 5 without any root.
+    fn tree() -> Tree {
+        Tree::new([
+            (1, None),
+            (2, Some(1)),
+            (3, Some(2)),
+            (4, Some(1)),
+            (5, Some(7)),
+            (6, Some(5)),
+            (7, Some(6)),
+        ])
+    }
+
+    #[test]
+    fn roots_leave_out_cycles() {
+        assert_eq!(tree().roots(), vec![1]);
+        assert_eq!(Tree::new([(1, Some(9)), (2, Some(1))]).roots(), vec![1]);
+    }
+
+    #[test]
+    fn descendants_include_the_record_itself() {
+        let tree = tree();
+        assert_eq!(tree.descendants(1), vec![1, 2, 4, 3]);
+        assert_eq!(tree.descendants(3), vec![3]);
+        assert_eq!(tree.descendants(42), vec![42]);
+    }
+
+    #[test]
+    fn descendants_walk_a_cycle_once() {
+        assert_eq!(tree().descendants(5), vec![5, 6, 7]);
+        assert_eq!(Tree::new([(1, Some(1))]).descendants(1), vec![1]);
+    }
+
+    #[test]
+    fn ancestors_start_at_the_root() {
+        let tree = tree();
+        assert_eq!(tree.ancestors(3), vec![1, 2]);
+        assert!(tree.ancestors(1).is_empty());
+    }
+
+    #[test]
+    fn ancestors_stop_at_a_cycle() {
+        assert_eq!(tree().ancestors(5), vec![6, 7]);
+        assert!(Tree::new([(1, Some(1))]).ancestors(1).is_empty());
+    }
+
+    #[test]
+    fn check_parent_rejects_cycles() {
+        let tree = tree();
+        assert!(tree.check_parent(2, Some(4)).is_ok());
+        assert!(tree.check_parent(2, None).is_ok());
+        assert!(tree.check_parent(2, Some(2)).is_err());
+        assert!(tree.check_parent(1, Some(3)).is_err());
+        assert!(tree.check_parent(5, Some(7)).is_err());
+        assert!(tree.check_parent(5, Some(1)).is_ok());
+    }
+
+    #[test]
+    fn subtree_totals_sum_descendants() {
+        let values = HashMap::from([(1, 1), (2, 10), (3, 100), (4, 1000)]);
+        let totals = tree().subtree_totals(&values);
+        assert_eq!(totals[&1], 1111);
+        assert_eq!(totals[&2], 110);
+        assert_eq!(totals[&3], 100);
+        assert_eq!(totals[&4], 1000);
+    }
+
+    #[test]
+    fn subtree_totals_count_a_cycle_once() {
+        let values = HashMap::from([(5, 1), (6, 10), (7, 100)]);
+        let totals = tree().subtree_totals(&values);
+        assert_eq!(totals[&5], 111);
+        assert_eq!(totals.len(), 7);
+    }
+
+    #[test]
+    fn nest_shows_each_record_once() {
+        let tree = Tree::new([(1, None), (2, Some(1)), (3, Some(2))]);
+        let count = |_: i32, children: Vec<usize>| Some(1 + children.iter().sum::<usize>());
+        assert_eq!(tree.nest(&[1], None, &count), vec![3]);
+        assert_eq!(tree.nest(&[1], Some(1), &count), vec![2]);
+        assert_eq!(tree.nest(&[1, 2], None, &count), vec![3]);
+    }
+}
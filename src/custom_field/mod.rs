@@ -0,0 +1,402 @@
+use crate::db;
+use crate::errors::{AppError, AppResult, CommonResponse};
+use crate::repo::Tenant;
+use crate::utils::JwtClaims;
+use crate::DB;
+
+use axum::debug_handler;
+use axum::extract::{Path, Query};
+use axum::Json;
+use chrono::{DateTime, NaiveDate};
+use prisma_client_rust::Direction;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+fn value_matches(definition: &db::custom_field_definition::Data, value: &Value) -> bool {
+    match definition.field_type {
+        db::CustomFieldType::Text => value.is_string(),
+        db::CustomFieldType::Number => value.is_number(),
+        db::CustomFieldType::Boolean => value.is_boolean(),
+        db::CustomFieldType::Date => value
+            .as_str()
+            .map(|s| {
+                NaiveDate::parse_from_str(s, "%Y-%m-%d").is_ok()
+                    || DateTime::parse_from_rfc3339(s).is_ok()
+            })
+            .unwrap_or(false),
+        db::CustomFieldType::Enum => value
+            .as_str()
+            .map(|s| definition.options.iter().any(|o| o == s))
+            .unwrap_or(false),
+    }
+}
+
+/// The field definitions of a company for one entity type.
+pub struct CustomFieldDefinitions(Vec<db::custom_field_definition::Data>);
+
+impl CustomFieldDefinitions {
+    pub async fn load(tenant: Tenant<'_>, entity: db::CustomFieldEntity) -> AppResult<Self> {
+        Ok(Self(
+            tenant
+                .custom_field_definition()
+                .find_many(vec![db::custom_field_definition::entity::equals(entity)])
+                .order_by(db::custom_field_definition::position::order(Direction::Asc))
+                .exec()
+                .await?,
+        ))
+    }
+
+    /// The keys of the fields in display order.
+    pub fn keys(&self) -> Vec<String> {
+        self.0.iter().map(|d| d.field_key.clone()).collect()
+    }
+
+    fn get(&self, key: &str) -> Option<&db::custom_field_definition::Data> {
+        self.0.iter().find(|d| d.field_key == key)
+    }
+
+    /// Convert the text of a spreadsheet cell into the JSON value of a field,
+    /// anything which does not fit the type is kept as text and left to
+    /// `validate` to reject.
+    pub fn parse(&self, key: &str, text: &str) -> Value {
+        let field_type = self.get(key).map(|d| d.field_type);
+        match field_type {
+            Some(db::CustomFieldType::Number) => text
+                .parse::<f64>()
+                .ok()
+                .and_then(serde_json::Number::from_f64)
+                .map(Value::Number),
+            Some(db::CustomFieldType::Boolean) => match text.to_lowercase().as_str() {
+                "true" | "yes" | "1" => Some(Value::Bool(true)),
+                "false" | "no" | "0" => Some(Value::Bool(false)),
+                _ => None,
+            },
+            _ => None,
+        }
+        .unwrap_or_else(|| Value::String(text.to_string()))
+    }
+
+    /// Validate `values` against the definitions and merge them over the
+    /// `existing` values of the record. A `null` value removes a field,
+    /// values of fields which are no longer defined are dropped.
+    pub fn validate(&self, values: Option<Value>, existing: Option<&Value>) -> AppResult<Value> {
+        let mut merged: Map<String, Value> = match existing {
+            Some(Value::Object(m)) => m
+                .iter()
+                .filter(|(k, _)| self.get(k).is_some())
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect(),
+            _ => Map::new(),
+        };
+        let mut invalid = vec![];
+        match values {
+            None | Some(Value::Null) => {}
+            Some(Value::Object(m)) => {
+                for (k, v) in m {
+                    match self.get(&k) {
+                        _ if v.is_null() => {
+                            merged.remove(&k);
+                        }
+                        Some(d) if value_matches(d, &v) => {
+                            merged.insert(k, v);
+                        }
+                        _ => invalid.push(format!("custom_fields.{}", k)),
+                    }
+                }
+            }
+            Some(_) => invalid.push("custom_fields".to_string()),
+        }
+        for d in self.0.iter().filter(|d| d.required) {
+            if !merged.contains_key(&d.field_key) {
+                invalid.push(format!("custom_fields.{}", d.field_key));
+            }
+        }
+        if invalid.is_empty() {
+            Ok(Value::Object(merged))
+        } else {
+            Err(AppError::InvalidFields(invalid))
+        }
+    }
+}
+
+/// Validate `values` against the company's field definitions for `entity`
+/// and merge them over the `existing` values of the record.
+pub async fn validate_custom_fields(
+    tenant: Tenant<'_>,
+    entity: db::CustomFieldEntity,
+    values: Option<Value>,
+    existing: Option<&Value>,
+) -> AppResult<Value> {
+    CustomFieldDefinitions::load(tenant, entity)
+        .await?
+        .validate(values, existing)
+}
+
+fn check_definition(
+    field_key: Option<&str>,
+    field_type: db::CustomFieldType,
+    options: &[String],
+) -> AppResult<()> {
+    let mut invalid = vec![];
+    if let Some(key) = field_key {
+        if key.is_empty() || !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            invalid.push("field_key".to_string());
+        }
+    }
+    if field_type == db::CustomFieldType::Enum && options.is_empty() {
+        invalid.push("options".to_string());
+    }
+    if invalid.is_empty() {
+        Ok(())
+    } else {
+        Err(AppError::InvalidFields(invalid))
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CustomFieldQuery {
+    pub entity: Option<db::CustomFieldEntity>,
+}
+
+/// Definitions are readable by every user so that forms can be rendered.
+#[debug_handler]
+pub async fn list_custom_fields(
+    c: JwtClaims,
+    Query(q): Query<CustomFieldQuery>,
+) -> AppResult<Json<CommonResponse<Vec<db::custom_field_definition::Data>>>> {
+    let client = DB.get().unwrap();
+    let filters = match q.entity {
+        Some(entity) => vec![db::custom_field_definition::entity::equals(entity)],
+        None => vec![],
+    };
+    CommonResponse::json_data(
+        c.tenant(client)
+            .custom_field_definition()
+            .find_many(filters)
+            .order_by(db::custom_field_definition::position::order(Direction::Asc))
+            .exec()
+            .await?,
+    )
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateCustomFieldInfo {
+    pub entity: db::CustomFieldEntity,
+    pub field_key: String,
+    pub label: String,
+    pub field_type: db::CustomFieldType,
+    #[serde(default)]
+    pub required: bool,
+    /// the allowed values of an ENUM field
+    #[serde(default)]
+    pub options: Vec<String>,
+    #[serde(default)]
+    pub position: i32,
+}
+
+#[debug_handler]
+pub async fn create_custom_field(
+    c: JwtClaims,
+    Json(payload): Json<CreateCustomFieldInfo>,
+) -> AppResult<Json<CommonResponse<db::custom_field_definition::Data>>> {
+    c.check_module_privilige(db::Module::Admin, db::PrivilegeType::Edit)
+        .await?;
+    check_definition(
+        Some(&payload.field_key),
+        payload.field_type,
+        &payload.options,
+    )?;
+    let client = DB.get().unwrap();
+    CommonResponse::json_data(
+        c.tenant(client)
+            .custom_field_definition()
+            .create(|definitions, company| {
+                definitions.create(
+                    company,
+                    payload.entity,
+                    payload.field_key,
+                    payload.label,
+                    payload.field_type,
+                    vec![
+                        db::custom_field_definition::required::set(payload.required),
+                        db::custom_field_definition::options::set(payload.options),
+                        db::custom_field_definition::position::set(payload.position),
+                    ],
+                )
+            })
+            .exec()
+            .await?,
+    )
+}
+
+db::custom_field_definition::partial!(
+    UpdateCustomFieldInfo {
+        label
+        required
+        options
+        position
+    }
+);
+
+#[debug_handler]
+pub async fn update_custom_field(
+    Path(id): Path<i32>,
+    c: JwtClaims,
+    Json(payload): Json<UpdateCustomFieldInfo>,
+) -> AppResult<Json<CommonResponse<db::custom_field_definition::Data>>> {
+    c.check_module_privilige(db::Module::Admin, db::PrivilegeType::Edit)
+        .await?;
+    let client = DB.get().unwrap();
+    let tenant = c.tenant(client);
+    let definition = tenant.custom_field_definition().get(id).await?;
+    if let Some(options) = &payload.options {
+        check_definition(None, definition.field_type, options)?;
+    }
+    CommonResponse::json_data(
+        tenant
+            .custom_field_definition()
+            .update(id, payload.to_params())
+            .await?
+            .exec()
+            .await?,
+    )
+}
+
+/// Values already stored for the field are dropped the next time the record
+/// is updated, the ones left until then are back when the field is restored.
+#[debug_handler]
+pub async fn delete_custom_field(
+    Path(id): Path<i32>,
+    c: JwtClaims,
+) -> AppResult<Json<CommonResponse<db::custom_field_definition::Data>>> {
+    c.check_module_privilige(db::Module::Admin, db::PrivilegeType::Edit)
+        .await?;
+    let client = DB.get().unwrap();
+    CommonResponse::json_data(
+        c.tenant(client)
+            .custom_field_definition()
+            .soft_delete(id)
+            .await?
+            .exec()
+            .await?,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn definition(
+        field_key: &str,
+        field_type: &str,
+        required: bool,
+        options: &[&str],
+    ) -> db::custom_field_definition::Data {
+        serde_json::from_value(json!({
+            "id": 1,
+            "created_at": "2023-04-22T00:00:00+00:00",
+            "updated_at": "2023-04-22T00:00:00+00:00",
+            "deleted_at": null,
+            "company": null,
+            "company_id": 1,
+            "entity": "ASSET",
+            "field_key": field_key,
+            "label": field_key,
+            "field_type": field_type,
+            "required": required,
+            "options": options,
+            "position": 0,
+        }))
+        .unwrap()
+    }
+
+    fn definitions() -> CustomFieldDefinitions {
+        CustomFieldDefinitions(vec![
+            definition("serial", "TEXT", true, &[]),
+            definition("hours", "NUMBER", false, &[]),
+            definition("installed", "DATE", false, &[]),
+            definition("color", "ENUM", false, &["red", "blue"]),
+            definition("spare", "BOOLEAN", false, &[]),
+        ])
+    }
+
+    fn invalid_fields(result: AppResult<Value>) -> Vec<String> {
+        match result {
+            Err(AppError::InvalidFields(mut fields)) => {
+                fields.sort();
+                fields
+            }
+            other => panic!("expected invalid fields, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn validate_merges_over_existing_values() {
+        let existing = json!({"serial": "A1", "hours": 3, "dropped": true});
+        let values = json!({"hours": 5, "color": "red", "installed": "2023-04-22"});
+        assert_eq!(
+            definitions()
+                .validate(Some(values), Some(&existing))
+                .unwrap(),
+            json!({"serial": "A1", "hours": 5, "color": "red", "installed": "2023-04-22"})
+        );
+    }
+
+    #[test]
+    fn validate_removes_null_values() {
+        let existing = json!({"serial": "A1", "hours": 3});
+        assert_eq!(
+            definitions()
+                .validate(Some(json!({"hours": null})), Some(&existing))
+                .unwrap(),
+            json!({"serial": "A1"})
+        );
+    }
+
+    #[test]
+    fn validate_rejects_values_of_the_wrong_type() {
+        let values = json!({
+            "serial": "A1",
+            "hours": "five",
+            "installed": "22.04.2023",
+            "color": "green",
+            "spare": "yes",
+            "unknown": 1,
+        });
+        assert_eq!(
+            invalid_fields(definitions().validate(Some(values), None)),
+            vec![
+                "custom_fields.color",
+                "custom_fields.hours",
+                "custom_fields.installed",
+                "custom_fields.spare",
+                "custom_fields.unknown",
+            ]
+        );
+    }
+
+    #[test]
+    fn validate_requires_required_fields() {
+        assert_eq!(
+            invalid_fields(definitions().validate(None, None)),
+            vec!["custom_fields.serial"]
+        );
+        let existing = json!({"serial": "A1"});
+        assert_eq!(
+            invalid_fields(definitions().validate(Some(json!({"serial": null})), Some(&existing))),
+            vec!["custom_fields.serial"]
+        );
+        assert!(definitions().validate(None, Some(&existing)).is_ok());
+    }
+
+    #[test]
+    fn validate_rejects_values_which_are_no_object() {
+        assert_eq!(
+            invalid_fields(
+                definitions().validate(Some(json!(["A1"])), Some(&json!({"serial": "A1"})))
+            ),
+            vec!["custom_fields"]
+        );
+    }
+}
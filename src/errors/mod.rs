@@ -1,6 +1,7 @@
 use axum::{http::StatusCode, response::IntoResponse, Json};
-use prisma_client_rust::QueryError;
+use prisma_client_rust::{prisma_errors::query_engine::UniqueKeyViolation, QueryError};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use thiserror::Error;
 
 pub type AppResult<T> = Result<T, AppError>;
@@ -15,17 +16,46 @@ pub enum AppError {
     DbError(#[from] QueryError),
     #[error("failed due to: `{error:?}`")]
     Custom { status_code: u16, error: String },
+    #[error("you are not allowed to set: {}", .0.join(", "))]
+    ForbiddenFields(Vec<String>),
+    #[error("referenced records do not exist in your company: {}", .0.join(", "))]
+    InvalidReferences(Vec<String>),
+    #[error("invalid values for: {}", .0.join(", "))]
+    InvalidFields(Vec<String>),
     #[error(transparent)]
     Other(#[from] anyhow::Error),
 }
 
 impl IntoResponse for AppError {
     fn into_response(self) -> axum::response::Response {
+        let message = self.to_string();
+        let mut fields = None;
         let (code, err) = match self {
             AppError::Custom { status_code, error } => {
                 tracing::error!("return status code {} with error {}", status_code, error);
                 (StatusCode::from_u16(status_code).unwrap(), error)
             }
+            AppError::ForbiddenFields(f) => {
+                tracing::error!("return status code 403 with error {}", message);
+                fields = Some(f);
+                (StatusCode::FORBIDDEN, message)
+            }
+            AppError::InvalidReferences(f) | AppError::InvalidFields(f) => {
+                tracing::error!("return status code 422 with error {}", message);
+                fields = Some(f);
+                (StatusCode::UNPROCESSABLE_ENTITY, message)
+            }
+            AppError::DbError(q) if q.is_prisma_error::<UniqueKeyViolation>() => {
+                tracing::error!("unique constraint violated: {}", q);
+                let f = violated_fields(&q);
+                let err = if f.is_empty() {
+                    "a record with the same values already exists".to_owned()
+                } else {
+                    format!("a record with the same {} already exists", f.join(", "))
+                };
+                fields = Some(f);
+                (StatusCode::CONFLICT, err)
+            }
             AppError::DbError(q) => {
                 tracing::error!("an error occured during query execution: {}", q);
                 (
@@ -42,6 +72,7 @@ impl IntoResponse for AppError {
             code,
             Json(CommonResponse::<()> {
                 error: Some(err),
+                fields,
                 data: None,
             }),
         )
@@ -49,10 +80,34 @@ impl IntoResponse for AppError {
     }
 }
 
+/// The fields of the unique constraint a query violated as reported by the
+/// query engine, `company_id` is left out since every code is only unique
+/// within a company.
+fn violated_fields(q: &QueryError) -> Vec<String> {
+    let target = match q {
+        QueryError::Execute(e) => e.as_known().map(|k| k.meta["target"].clone()),
+        _ => None,
+    };
+    match target {
+        Some(Value::Array(fields)) => fields
+            .iter()
+            .filter_map(|f| f.as_str())
+            .filter(|f| *f != "company_id")
+            .map(String::from)
+            .collect(),
+        // some constraints are only reported by the name of their index
+        Some(Value::String(index)) => vec![index],
+        _ => vec![],
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CommonResponse<T> {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub error: Option<String>,
+    /// the request fields an error refers to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fields: Option<Vec<String>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     #[serde(bound(deserialize = "T: Serialize + Deserialize<'de>"))]
     pub data: Option<T>,
@@ -62,6 +117,7 @@ impl<T> CommonResponse<T> {
     pub fn json_data(d: T) -> AppResult<Json<CommonResponse<T>>> {
         Ok(Json(CommonResponse {
             error: None,
+            fields: None,
             data: Some(d),
         }))
     }
@@ -0,0 +1,512 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::future::Future;
+use std::io;
+use std::sync::Arc;
+
+use axum::body::{Bytes, StreamBody};
+use axum::debug_handler;
+use axum::extract::Query;
+use axum::http::header;
+use axum::response::{IntoResponse, Response};
+use futures::{stream, StreamExt};
+use prisma_client_rust::Direction;
+use rust_xlsxwriter::Workbook;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::assets::tree::location_tree;
+use crate::assets::{AssetFilter, AssetListQuery, TreeQuery};
+use crate::custom_field::CustomFieldDefinitions;
+use crate::errors::{AppError, AppResult};
+use crate::maintainance_request::{MrFilter, MrListQuery};
+use crate::repo::Tenant;
+use crate::utils::JwtClaims;
+use crate::{db, DB};
+
+/// The number of records read per query while an export is written.
+const BATCH_SIZE: i64 = 500;
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Csv,
+    Xlsx,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExportQuery {
+    #[serde(default)]
+    pub format: ExportFormat,
+}
+
+/// The rows of one batch and the cursor of the following batch, if any.
+type Batch = AppResult<(Vec<Vec<String>>, Option<i32>)>;
+
+/// Split the extra row fetched with `take(BATCH_SIZE + 1)` off a batch.
+fn batch<T>(mut items: Vec<T>, id: impl Fn(&T) -> i32) -> (Vec<T>, Option<i32>) {
+    let next = if items.len() as i64 > BATCH_SIZE {
+        items.truncate(BATCH_SIZE as usize);
+        items.last().map(id)
+    } else {
+        None
+    };
+    (items, next)
+}
+
+/// Spreadsheet applications run cells starting with one of these as formulas.
+pub const FORMULA_PREFIXES: [char; 6] = ['=', '+', '-', '@', '\t', '\r'];
+
+/// Prefix CSV text which would run as a formula with a `'` so that it is shown
+/// as is, numbers such as `-5` are left alone. Text already starting with a
+/// `'` gets one too, so that the import can tell which ones to drop. XLSX
+/// cells are written as strings, which never run.
+pub fn escape_formula(value: &str) -> Cow<str> {
+    if value.starts_with('\'')
+        || value.starts_with(&FORMULA_PREFIXES[..]) && value.parse::<f64>().is_err()
+    {
+        Cow::Owned(format!("'{}", value))
+    } else {
+        Cow::Borrowed(value)
+    }
+}
+
+fn csv_bytes(rows: &[Vec<String>]) -> io::Result<Bytes> {
+    let mut writer = csv::Writer::from_writer(vec![]);
+    for row in rows {
+        writer.write_record(row.iter().map(|v| escape_formula(v).into_owned()))?;
+    }
+    writer
+        .into_inner()
+        .map(Bytes::from)
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))
+}
+
+fn export_failed(e: impl std::fmt::Display) -> AppError {
+    AppError::Custom {
+        status_code: 500,
+        error: format!("failed to write the export: {}", e),
+    }
+}
+
+/// Respond with the rows read batch by batch through `next`. CSV is streamed
+/// while it is read, XLSX has to be built in memory as a whole.
+async fn export<F, Fut>(
+    name: &str,
+    format: ExportFormat,
+    headers: Vec<String>,
+    next: F,
+) -> AppResult<Response>
+where
+    F: Fn(Option<i32>) -> Fut + Send + 'static,
+    Fut: Future<Output = Batch> + Send + 'static,
+{
+    match format {
+        ExportFormat::Csv => {
+            let header = stream::once(async move { csv_bytes(&[headers]) });
+            // `None` once the last batch was read
+            let rows = stream::unfold(Some(None), move |cursor: Option<Option<i32>>| {
+                let fetch = cursor.map(&next);
+                async move {
+                    match fetch?.await {
+                        Ok((rows, following)) => Some((csv_bytes(&rows), following.map(Some))),
+                        Err(e) => Some((
+                            Err(io::Error::new(io::ErrorKind::Other, e.to_string())),
+                            None,
+                        )),
+                    }
+                }
+            });
+            Ok((
+                [
+                    (header::CONTENT_TYPE, "text/csv".to_string()),
+                    (
+                        header::CONTENT_DISPOSITION,
+                        format!("attachment; filename=\"{}.csv\"", name),
+                    ),
+                ],
+                StreamBody::new(header.chain(rows)),
+            )
+                .into_response())
+        }
+        ExportFormat::Xlsx => {
+            let mut workbook = Workbook::new();
+            let sheet = workbook.add_worksheet();
+            let mut cursor = None;
+            let mut line = 0;
+            let mut write = |row: &[String]| -> AppResult<()> {
+                for (col, value) in row.iter().enumerate() {
+                    sheet
+                        .write_string(line, col as u16, value)
+                        .map_err(export_failed)?;
+                }
+                line += 1;
+                Ok(())
+            };
+            write(&headers)?;
+            loop {
+                let (rows, following) = next(cursor).await?;
+                for row in &rows {
+                    write(row)?;
+                }
+                match following {
+                    Some(c) => cursor = Some(c),
+                    None => break,
+                }
+            }
+            let bytes = workbook.save_to_buffer().map_err(export_failed)?;
+            Ok((
+                [
+                    (
+                        header::CONTENT_TYPE,
+                        "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet"
+                            .to_string(),
+                    ),
+                    (
+                        header::CONTENT_DISPOSITION,
+                        format!("attachment; filename=\"{}.xlsx\"", name),
+                    ),
+                ],
+                bytes,
+            )
+                .into_response())
+        }
+    }
+}
+
+fn custom_field_text(values: &Value, key: &str) -> String {
+    match values.get(key) {
+        Some(Value::String(s)) => s.clone(),
+        Some(Value::Null) | None => String::new(),
+        Some(v) => v.to_string(),
+    }
+}
+
+db::asset::select! { asset_export {
+    id
+    asset_code
+    asset_name
+    asset_description
+    asset_location: select {
+        location_code
+        location_name
+    }
+    asset_status: select {
+        status_code
+        status_name
+    }
+    parent_asset: select {
+        asset_code
+    }
+    custom_fields
+    created_at
+    updated_at
+}}
+
+/// Takes the filters of the asset listing. The location columns are only
+/// included for users who may view locations.
+#[debug_handler]
+pub async fn export_assets(
+    c: JwtClaims,
+    Query(e): Query<ExportQuery>,
+    Query(q): Query<AssetListQuery>,
+) -> AppResult<Response> {
+    c.check_module_privilige(db::Module::Asset, db::PrivilegeType::View)
+        .await?;
+    let with_location = c
+        .check_module_privilige(db::Module::Location, db::PrivilegeType::View)
+        .await
+        .is_ok();
+    let client = DB.get().unwrap();
+    let company_id = c.company_id;
+    let keys = CustomFieldDefinitions::load(c.tenant(client), db::CustomFieldEntity::Asset)
+        .await?
+        .keys();
+    let filter = AssetFilter::resolve(client, company_id, q).await?;
+
+    let mut headers = vec!["asset_code", "asset_name", "asset_description"];
+    if with_location {
+        headers.extend(["location_code", "location_name"]);
+    }
+    headers.extend(["status_code", "status_name", "parent_code"]);
+    let headers = headers
+        .into_iter()
+        .map(str::to_string)
+        .chain(keys.iter().map(|k| format!("custom_fields.{}", k)))
+        .chain(["created_at".to_string(), "updated_at".to_string()])
+        .collect();
+
+    export("assets", e.format, headers, move |cursor| {
+        let mut query = Tenant::new(client, company_id)
+            .asset()
+            .find_many(filter.params())
+            .take(BATCH_SIZE + 1);
+        for order in filter.order_by() {
+            query = query.order_by(order);
+        }
+        if let Some(cursor) = cursor {
+            query = query.cursor(db::asset::id::equals(cursor)).skip(1);
+        }
+        let keys = keys.clone();
+        async move {
+            let (found, next) = batch(query.select(asset_export::select()).exec().await?, |a| a.id);
+            let rows = found
+                .into_iter()
+                .map(|a| {
+                    let mut row = vec![a.asset_code, a.asset_name, a.asset_description];
+                    if with_location {
+                        row.extend([
+                            a.asset_location.location_code,
+                            a.asset_location.location_name,
+                        ]);
+                    }
+                    row.extend([
+                        a.asset_status.status_code,
+                        a.asset_status.status_name,
+                        a.parent_asset.map(|p| p.asset_code).unwrap_or_default(),
+                    ]);
+                    row.extend(keys.iter().map(|k| custom_field_text(&a.custom_fields, k)));
+                    row.extend([a.created_at.to_rfc3339(), a.updated_at.to_rfc3339()]);
+                    row
+                })
+                .collect();
+            Ok((rows, next))
+        }
+    })
+    .await
+}
+
+db::asset_location::select! { location_export {
+    id
+    location_code
+    location_name
+    location_description
+    parent_id
+}}
+
+/// Takes the `root_id` of the location tree to export a single subtree, the
+/// path lists the names from the root down to the location itself.
+#[debug_handler]
+pub async fn export_locations(
+    c: JwtClaims,
+    Query(e): Query<ExportQuery>,
+    Query(q): Query<TreeQuery>,
+) -> AppResult<Response> {
+    c.check_module_privilige(db::Module::Location, db::PrivilegeType::View)
+        .await?;
+    let client = DB.get().unwrap();
+    let company_id = c.company_id;
+    let tenant = c.tenant(client);
+    if let Some(id) = q.root_id {
+        tenant.asset_location().ensure(id).await?;
+    }
+    let tree = Arc::new(location_tree(tenant).await?);
+    let locations: Arc<HashMap<i32, (String, String)>> = Arc::new(
+        tenant
+            .asset_location()
+            .find_many(vec![])
+            .select(location_export::select())
+            .exec()
+            .await?
+            .into_iter()
+            .map(|l| (l.id, (l.location_code, l.location_name)))
+            .collect(),
+    );
+    let subtree = q.root_id.map(|id| tree.descendants(id));
+
+    let headers = [
+        "location_code",
+        "location_name",
+        "location_description",
+        "parent_code",
+        "path",
+    ]
+    .into_iter()
+    .map(str::to_string)
+    .collect();
+
+    export("locations", e.format, headers, move |cursor| {
+        let mut filters = vec![];
+        if let Some(ids) = &subtree {
+            filters.push(db::asset_location::id::in_vec(ids.clone()));
+        }
+        let mut query = Tenant::new(client, company_id)
+            .asset_location()
+            .find_many(filters)
+            .order_by(db::asset_location::id::order(Direction::Asc))
+            .take(BATCH_SIZE + 1);
+        if let Some(cursor) = cursor {
+            query = query.cursor(db::asset_location::id::equals(cursor)).skip(1);
+        }
+        let tree = tree.clone();
+        let locations = locations.clone();
+        async move {
+            let (found, next) = batch(query.select(location_export::select()).exec().await?, |l| {
+                l.id
+            });
+            let rows = found
+                .into_iter()
+                .map(|l| {
+                    let path: Vec<&str> = tree
+                        .ancestors(l.id)
+                        .iter()
+                        .filter_map(|id| locations.get(id).map(|(_, name)| name.as_str()))
+                        .chain([l.location_name.as_str()])
+                        .collect();
+                    let path = path.join(" / ");
+                    let parent_code = l
+                        .parent_id
+                        .and_then(|id| locations.get(&id))
+                        .map(|(code, _)| code.clone())
+                        .unwrap_or_default();
+                    vec![
+                        l.location_code,
+                        l.location_name,
+                        l.location_description,
+                        parent_code,
+                        path,
+                    ]
+                })
+                .collect();
+            Ok((rows, next))
+        }
+    })
+    .await
+}
+
+db::maintainance_request::select! { mr_export {
+    id
+    mr_name
+    asset: select {
+        asset_code
+        asset_name
+    }
+    mr_status: select {
+        status_name
+    }
+    mr_category: select {
+        category_name
+    }
+    mr_priority: select {
+        priority_name
+    }
+    mr_failure_impact: select {
+        failure_impact_name
+    }
+    mr_failure_mode: select {
+        failure_mode_name
+    }
+    mr_reporter: select {
+        username
+    }
+    assignee: select {
+        username
+    }
+    mr_state
+    mr_error_code
+    mr_description
+    created_at
+    updated_at
+}}
+
+/// Takes the filters of the maintenance request listing.
+#[debug_handler]
+pub async fn export_mrs(
+    c: JwtClaims,
+    Query(e): Query<ExportQuery>,
+    Query(q): Query<MrListQuery>,
+) -> AppResult<Response> {
+    c.check_module_privilige(db::Module::MaintainanceRequest, db::PrivilegeType::View)
+        .await?;
+    let client = DB.get().unwrap();
+    let company_id = c.company_id;
+    let filter = MrFilter::resolve(client, company_id, q).await?;
+
+    let headers = [
+        "id",
+        "mr_name",
+        "asset_code",
+        "asset_name",
+        "status",
+        "category",
+        "priority",
+        "failure_impact",
+        "failure_mode",
+        "reporter",
+        "assignee",
+        "state",
+        "mr_error_code",
+        "mr_description",
+        "created_at",
+        "updated_at",
+    ]
+    .into_iter()
+    .map(str::to_string)
+    .collect();
+
+    export("maintenance_requests", e.format, headers, move |cursor| {
+        let mut query = Tenant::new(client, company_id)
+            .maintainance_request()
+            .find_many(filter.params())
+            .take(BATCH_SIZE + 1);
+        for order in filter.order_by() {
+            query = query.order_by(order);
+        }
+        if let Some(cursor) = cursor {
+            query = query
+                .cursor(db::maintainance_request::id::equals(cursor))
+                .skip(1);
+        }
+        async move {
+            let (found, next) = batch(query.select(mr_export::select()).exec().await?, |m| m.id);
+            let rows = found
+                .into_iter()
+                .map(|m| {
+                    vec![
+                        m.id.to_string(),
+                        m.mr_name,
+                        m.asset.asset_code,
+                        m.asset.asset_name,
+                        m.mr_status.status_name,
+                        m.mr_category.category_name,
+                        m.mr_priority.priority_name,
+                        m.mr_failure_impact.failure_impact_name,
+                        m.mr_failure_mode.failure_mode_name,
+                        m.mr_reporter.username,
+                        m.assignee.map(|a| a.username).unwrap_or_default(),
+                        format!("{:?}", m.mr_state).to_lowercase(),
+                        m.mr_error_code,
+                        m.mr_description,
+                        m.created_at.to_rfc3339(),
+                        m.updated_at.to_rfc3339(),
+                    ]
+                })
+                .collect();
+            Ok((rows, next))
+        }
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escape_formula_prefixes_formulas() {
+        assert_eq!(escape_formula("=SUM(A1:A2)"), "'=SUM(A1:A2)");
+        assert_eq!(escape_formula("+cmd"), "'+cmd");
+        assert_eq!(escape_formula("@A1"), "'@A1");
+        assert_eq!(escape_formula("\tx"), "'\tx");
+        assert_eq!(escape_formula("'quoted"), "''quoted");
+    }
+
+    #[test]
+    fn escape_formula_leaves_the_rest_alone() {
+        assert!(matches!(escape_formula("pump"), Cow::Borrowed("pump")));
+        assert_eq!(escape_formula("-5"), "-5");
+        assert_eq!(escape_formula("+1.5"), "+1.5");
+        assert_eq!(escape_formula(""), "");
+    }
+}
@@ -0,0 +1,566 @@
+use std::collections::{HashMap, HashSet};
+use std::io::Cursor;
+
+use axum::body::Bytes;
+use axum::debug_handler;
+use axum::extract::Query;
+use axum::Json;
+use calamine::{Reader, Xlsx};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use tracing::info;
+
+use crate::assets::history::{record_asset_events, AssetChange};
+use crate::custom_field::CustomFieldDefinitions;
+use crate::errors::{AppError, AppResult, CommonResponse};
+use crate::export::FORMULA_PREFIXES;
+use crate::repo::Tenant;
+use crate::utils::JwtClaims;
+use crate::{db, DB};
+
+/// Imports of thousands of rows run far longer than the default transaction
+/// timeout.
+const IMPORT_TIMEOUT_MS: u64 = 120_000;
+const CUSTOM_FIELD_PREFIX: &str = "custom_fields.";
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportFormat {
+    #[default]
+    Csv,
+    Xlsx,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ImportQuery {
+    #[serde(default)]
+    pub format: ImportFormat,
+    /// only validate the rows and report the errors, nothing is written
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RowError {
+    /// the line of the row in the file, the header is line 1
+    pub row: usize,
+    pub field: String,
+    pub error: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ImportReport {
+    pub dry_run: bool,
+    pub rows: usize,
+    /// the number of valid rows, which are written unless this is a dry run
+    pub imported: usize,
+    pub errors: Vec<RowError>,
+}
+
+/// A data row of an uploaded sheet keyed by the headers of its columns.
+struct Row {
+    line: usize,
+    values: HashMap<String, String>,
+    errors: Vec<RowError>,
+}
+
+impl Row {
+    fn get(&self, column: &str) -> Option<String> {
+        self.values
+            .get(column)
+            .map(|v| v.trim())
+            .filter(|v| !v.is_empty())
+            .map(str::to_string)
+    }
+
+    fn error(&mut self, field: &str, error: impl Into<String>) {
+        self.errors.push(RowError {
+            row: self.line,
+            field: field.to_string(),
+            error: error.into(),
+        });
+    }
+
+    fn required(&mut self, column: &str) -> String {
+        self.get(column).unwrap_or_else(|| {
+            self.error(column, "is required");
+            String::new()
+        })
+    }
+
+    /// The values of the `custom_fields.<key>` columns, validated against
+    /// the definitions of the company.
+    fn custom_fields(&mut self, definitions: &CustomFieldDefinitions) -> Value {
+        let values: Map<String, Value> = self
+            .values
+            .iter()
+            .filter_map(|(column, text)| {
+                let key = column.strip_prefix(CUSTOM_FIELD_PREFIX)?;
+                let text = text.trim();
+                (!text.is_empty()).then(|| (key.to_string(), definitions.parse(key, text)))
+            })
+            .collect();
+        match definitions.validate(Some(Value::Object(values)), None) {
+            Ok(v) => v,
+            Err(AppError::InvalidFields(fields)) => {
+                for f in fields {
+                    self.error(&f, "missing or invalid value");
+                }
+                Value::Object(Map::new())
+            }
+            Err(e) => {
+                self.error("custom_fields", e.to_string());
+                Value::Object(Map::new())
+            }
+        }
+    }
+}
+
+fn unreadable(e: impl std::fmt::Display) -> AppError {
+    AppError::Custom {
+        status_code: 400,
+        error: format!("could not read the uploaded file: {}", e),
+    }
+}
+
+/// Undo the `'` a CSV export put in front of text which would run as a
+/// formula, so that exported files import unchanged.
+fn unescape_formula(value: String) -> String {
+    match value.strip_prefix('\'') {
+        Some(text) if text.starts_with('\'') || text.starts_with(&FORMULA_PREFIXES[..]) => {
+            text.to_string()
+        }
+        _ => value,
+    }
+}
+
+/// Written by Excel in front of the CSV files it saves as UTF-8.
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+fn read_rows(format: ImportFormat, body: &[u8]) -> AppResult<Vec<Row>> {
+    let mut lines: Vec<Vec<String>> = match format {
+        ImportFormat::Csv => csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .from_reader(body.strip_prefix(UTF8_BOM).unwrap_or(body))
+            .records()
+            .map(|r| r.map(|r| r.iter().map(|v| unescape_formula(v.to_string())).collect()))
+            .collect::<Result<_, _>>()
+            .map_err(unreadable)?,
+        ImportFormat::Xlsx => {
+            let mut workbook: Xlsx<_> = Xlsx::new(Cursor::new(body)).map_err(unreadable)?;
+            workbook
+                .worksheet_range_at(0)
+                .ok_or_else(|| unreadable("the workbook has no sheets"))?
+                .map_err(unreadable)?
+                .rows()
+                .map(|r| r.iter().map(|c| c.to_string()).collect())
+                .collect()
+        }
+    };
+    if lines.is_empty() {
+        return Err(unreadable("the file is empty"));
+    }
+    let headers: Vec<String> = lines
+        .remove(0)
+        .into_iter()
+        .map(|h| h.trim().to_string())
+        .collect();
+    Ok(lines
+        .into_iter()
+        .enumerate()
+        .filter(|(_, values)| values.iter().any(|v| !v.trim().is_empty()))
+        .map(|(i, values)| Row {
+            line: i + 2,
+            values: headers.iter().cloned().zip(values).collect(),
+            errors: vec![],
+        })
+        .collect())
+}
+
+/// A reference to a record which already exists, or to one created by an
+/// earlier row of the same import.
+enum Ref {
+    Existing(i32),
+    Imported(String),
+}
+
+impl Ref {
+    fn resolve(&self, created: &HashMap<String, i32>) -> i32 {
+        match self {
+            Ref::Existing(id) => *id,
+            Ref::Imported(code) => created[code],
+        }
+    }
+}
+
+/// Look up `code` in the company's records and the valid rows above.
+fn reference(
+    code: &str,
+    existing: &HashMap<String, i32>,
+    imported: &HashSet<String>,
+) -> Option<Ref> {
+    match existing.get(code) {
+        Some(id) => Some(Ref::Existing(*id)),
+        None if imported.contains(code) => Some(Ref::Imported(code.to_string())),
+        None => None,
+    }
+}
+
+/// Check the code in `column` is new and record the row as imported when it
+/// is valid, otherwise its errors are moved into `errors`. Codes of deleted
+/// records stay taken until they are purged.
+fn accept(
+    mut row: Row,
+    column: &str,
+    code: &str,
+    existing: &HashMap<String, i32>,
+    deleted: &HashSet<String>,
+    imported: &mut HashSet<String>,
+    errors: &mut Vec<RowError>,
+) -> bool {
+    if !code.is_empty()
+        && (existing.contains_key(code) || deleted.contains(code) || imported.contains(code))
+    {
+        row.error(column, format!("{} already exists", code));
+    }
+    if row.errors.is_empty() {
+        imported.insert(code.to_string());
+        true
+    } else {
+        errors.append(&mut row.errors);
+        false
+    }
+}
+
+db::asset_location::select! { location_code_out {
+    id
+    location_code
+}}
+
+db::asset_status::select! { status_code_out {
+    id
+    status_code
+}}
+
+db::asset::select! { asset_code_out {
+    id
+    asset_code
+}}
+
+struct AssetRow {
+    code: String,
+    name: String,
+    description: String,
+    location_id: i32,
+    status_id: i32,
+    parent: Option<Ref>,
+    custom_fields: Value,
+}
+
+/// Columns: `asset_code`, `asset_name`, `asset_description`, `location_code`,
+/// `status_code`, an optional `parent_code` and `custom_fields.<key>` for
+/// custom fields. The parent must exist or be on an earlier row.
+#[debug_handler]
+pub async fn import_assets(
+    c: JwtClaims,
+    Query(q): Query<ImportQuery>,
+    body: Bytes,
+) -> AppResult<Json<CommonResponse<ImportReport>>> {
+    c.check_module_privilige(db::Module::Asset, db::PrivilegeType::Edit)
+        .await?;
+    let rows = read_rows(q.format, &body)?;
+    let client = DB.get().unwrap();
+    let tenant = c.tenant(client);
+    let locations: HashMap<String, i32> = tenant
+        .asset_location()
+        .find_many(vec![])
+        .select(location_code_out::select())
+        .exec()
+        .await?
+        .into_iter()
+        .map(|l| (l.location_code, l.id))
+        .collect();
+    let statuses: HashMap<String, i32> = tenant
+        .asset_status()
+        .find_many(vec![])
+        .select(status_code_out::select())
+        .exec()
+        .await?
+        .into_iter()
+        .map(|s| (s.status_code, s.id))
+        .collect();
+    let assets: HashMap<String, i32> = tenant
+        .asset()
+        .find_many(vec![])
+        .select(asset_code_out::select())
+        .exec()
+        .await?
+        .into_iter()
+        .map(|a| (a.asset_code, a.id))
+        .collect();
+    let deleted: HashSet<String> = tenant
+        .asset()
+        .find_deleted(vec![])
+        .select(asset_code_out::select())
+        .exec()
+        .await?
+        .into_iter()
+        .map(|a| a.asset_code)
+        .collect();
+    let definitions = CustomFieldDefinitions::load(tenant, db::CustomFieldEntity::Asset).await?;
+
+    let total = rows.len();
+    let mut errors = vec![];
+    let mut imported = HashSet::new();
+    let mut valid = vec![];
+    for mut row in rows {
+        let code = row.required("asset_code");
+        let name = row.required("asset_name");
+        let description = row.get("asset_description").unwrap_or_default();
+        let location_code = row.required("location_code");
+        let location_id = locations.get(&location_code).copied();
+        if location_id.is_none() && !location_code.is_empty() {
+            row.error(
+                "location_code",
+                format!("unknown location {}", location_code),
+            );
+        }
+        let status_code = row.required("status_code");
+        let status_id = statuses.get(&status_code).copied();
+        if status_id.is_none() && !status_code.is_empty() {
+            row.error("status_code", format!("unknown status {}", status_code));
+        }
+        let parent = match row.get("parent_code") {
+            Some(p) => match reference(&p, &assets, &imported) {
+                Some(r) => Some(r),
+                None => {
+                    row.error("parent_code", format!("unknown asset {}", p));
+                    None
+                }
+            },
+            None => None,
+        };
+        let custom_fields = row.custom_fields(&definitions);
+        if accept(
+            row,
+            "asset_code",
+            &code,
+            &assets,
+            &deleted,
+            &mut imported,
+            &mut errors,
+        ) {
+            valid.push(AssetRow {
+                code,
+                name,
+                description,
+                location_id: location_id.unwrap(),
+                status_id: status_id.unwrap(),
+                parent,
+                custom_fields,
+            });
+        }
+    }
+
+    let report = ImportReport {
+        dry_run: q.dry_run,
+        rows: total,
+        imported: valid.len(),
+        errors,
+    };
+    if q.dry_run || valid.is_empty() {
+        return CommonResponse::json_data(report);
+    }
+    let (company_id, user_id) = (c.company_id, c.user_id);
+    client
+        ._transaction()
+        .with_timeout(IMPORT_TIMEOUT_MS)
+        .run(|client| async move {
+            let tenant = Tenant::new(&client, company_id);
+            let mut created = HashMap::new();
+            for a in valid {
+                let parent = a.parent.map(|p| p.resolve(&created));
+                let asset = tenant
+                    .asset()
+                    .create(|assets, company| {
+                        assets.create(
+                            company,
+                            a.code,
+                            a.name,
+                            a.description,
+                            db::asset_location::id::equals(a.location_id),
+                            db::asset_status::id::equals(a.status_id),
+                            vec![
+                                db::asset::asset_id::set(parent),
+                                db::asset::custom_fields::set(a.custom_fields),
+                            ],
+                        )
+                    })
+                    .exec()
+                    .await?;
+                record_asset_events(
+                    &client,
+                    company_id,
+                    user_id,
+                    asset.id,
+                    vec![AssetChange::created()],
+                )
+                .await?;
+                created.insert(asset.asset_code, asset.id);
+            }
+            Ok::<_, AppError>(())
+        })
+        .await?;
+    info!(
+        "imported {} assets into company {}",
+        report.imported, company_id
+    );
+    CommonResponse::json_data(report)
+}
+
+struct LocationRow {
+    code: String,
+    name: String,
+    description: String,
+    parent: Option<Ref>,
+}
+
+/// Columns: `location_code`, `location_name`, `location_description` and an
+/// optional `parent_code`, the parent must exist or be on an earlier row.
+#[debug_handler]
+pub async fn import_locations(
+    c: JwtClaims,
+    Query(q): Query<ImportQuery>,
+    body: Bytes,
+) -> AppResult<Json<CommonResponse<ImportReport>>> {
+    c.check_module_privilige(db::Module::Location, db::PrivilegeType::Edit)
+        .await?;
+    let rows = read_rows(q.format, &body)?;
+    let client = DB.get().unwrap();
+    let tenant = c.tenant(client);
+    let locations: HashMap<String, i32> = tenant
+        .asset_location()
+        .find_many(vec![])
+        .select(location_code_out::select())
+        .exec()
+        .await?
+        .into_iter()
+        .map(|l| (l.location_code, l.id))
+        .collect();
+    let deleted: HashSet<String> = tenant
+        .asset_location()
+        .find_deleted(vec![])
+        .select(location_code_out::select())
+        .exec()
+        .await?
+        .into_iter()
+        .map(|l| l.location_code)
+        .collect();
+
+    let total = rows.len();
+    let mut errors = vec![];
+    let mut imported = HashSet::new();
+    let mut valid = vec![];
+    for mut row in rows {
+        let code = row.required("location_code");
+        let name = row.required("location_name");
+        let description = row.get("location_description").unwrap_or_default();
+        let parent = match row.get("parent_code") {
+            Some(p) => match reference(&p, &locations, &imported) {
+                Some(r) => Some(r),
+                None => {
+                    row.error("parent_code", format!("unknown location {}", p));
+                    None
+                }
+            },
+            None => None,
+        };
+        if accept(
+            row,
+            "location_code",
+            &code,
+            &locations,
+            &deleted,
+            &mut imported,
+            &mut errors,
+        ) {
+            valid.push(LocationRow {
+                code,
+                name,
+                description,
+                parent,
+            });
+        }
+    }
+
+    let report = ImportReport {
+        dry_run: q.dry_run,
+        rows: total,
+        imported: valid.len(),
+        errors,
+    };
+    if q.dry_run || valid.is_empty() {
+        return CommonResponse::json_data(report);
+    }
+    let company_id = c.company_id;
+    client
+        ._transaction()
+        .with_timeout(IMPORT_TIMEOUT_MS)
+        .run(|client| async move {
+            let tenant = Tenant::new(&client, company_id);
+            let mut created = HashMap::new();
+            for l in valid {
+                let parent = l.parent.map(|p| p.resolve(&created));
+                let location = tenant
+                    .asset_location()
+                    .create(|locations, company| {
+                        locations.create(
+                            company,
+                            l.code,
+                            l.name,
+                            l.description,
+                            vec![db::asset_location::parent_id::set(parent)],
+                        )
+                    })
+                    .exec()
+                    .await?;
+                created.insert(location.location_code, location.id);
+            }
+            Ok::<_, AppError>(())
+        })
+        .await?;
+    info!(
+        "imported {} locations into company {}",
+        report.imported, company_id
+    );
+    CommonResponse::json_data(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::escape_formula;
+
+    #[test]
+    fn unescape_formula_undoes_escape_formula() {
+        for value in ["=A1", "@pump", "'quoted", "''twice", "-5", "plain", ""] {
+            assert_eq!(unescape_formula(escape_formula(value).into_owned()), value);
+        }
+    }
+
+    #[test]
+    fn unescape_formula_keeps_other_quotes() {
+        assert_eq!(unescape_formula("'plain".to_string()), "'plain");
+        assert_eq!(unescape_formula("'".to_string()), "'");
+    }
+
+    #[test]
+    fn read_rows_unescapes_csv_values() {
+        let rows = read_rows(ImportFormat::Csv, b"code,name\n'=1+1,'@pump\n,\n").unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].line, 2);
+        assert_eq!(rows[0].values["code"], "=1+1");
+        assert_eq!(rows[0].values["name"], "@pump");
+    }
+}
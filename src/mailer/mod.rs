@@ -0,0 +1,56 @@
+use std::path::PathBuf;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Mail {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+/// Delivery of outgoing emails, the implementation is picked at startup.
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, mail: &Mail) -> Result<()>;
+}
+
+/// Writes every mail as a file into a directory instead of sending it, meant
+/// for local development and testing.
+pub struct OutboxMailer {
+    dir: PathBuf,
+}
+
+impl OutboxMailer {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+}
+
+#[async_trait]
+impl Mailer for OutboxMailer {
+    async fn send(&self, mail: &Mail) -> Result<()> {
+        tokio::fs::create_dir_all(&self.dir).await?;
+        let recipient: String = mail
+            .to
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        let path = self.dir.join(format!(
+            "{}-{}.txt",
+            Utc::now().format("%Y%m%d%H%M%S%f"),
+            recipient
+        ));
+        let content = format!(
+            "To: {}\nSubject: {}\n\n{}\n",
+            mail.to, mail.subject, mail.body
+        );
+        tokio::fs::write(&path, content).await?;
+        info!("wrote mail to {} into {}", mail.to, path.display());
+        Ok(())
+    }
+}
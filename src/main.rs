@@ -6,14 +6,31 @@ mod user;
 use user::*;
 mod assets;
 use assets::*;
+mod custom_field;
+use custom_field::*;
 mod maintainance_request;
 use maintainance_request::*;
+mod platform;
+mod repo;
+use platform::*;
+mod role;
+use role::*;
 mod errors;
+mod export;
+use export::*;
+mod import;
+use import::*;
+mod mailer;
+use mailer::{Mailer, OutboxMailer};
+mod trash;
+use trash::*;
 mod utils;
+mod work_order;
+use work_order::*;
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use axum::{
-    routing::{get, post, put},
+    routing::{delete, get, post, put},
     Router,
 };
 use once_cell::sync::OnceCell;
@@ -22,6 +39,7 @@ use tracing::{info, Level};
 use tracing_subscriber::FmtSubscriber;
 
 static DB: OnceCell<PrismaClient> = OnceCell::new();
+static MAILER: OnceCell<Box<dyn Mailer>> = OnceCell::new();
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -36,6 +54,12 @@ async fn main() -> Result<()> {
     let client = db::new_client().await?;
     DB.set(client).unwrap();
 
+    let outbox = std::env::var("MAIL_OUTBOX_DIR").unwrap_or_else(|_| "outbox".to_string());
+    info!("Writing outgoing mails to {}", outbox);
+    MAILER
+        .set(Box::new(OutboxMailer::new(outbox)))
+        .map_err(|_| anyhow!("mailer already initialized"))?;
+
     let cors = CorsLayer::new()
         .allow_methods(Any)
         .allow_headers(Any)
@@ -44,6 +68,13 @@ async fn main() -> Result<()> {
     let user_router = Router::new()
         .route("/register", post(user_register))
         .route("/login", post(user_login))
+        .route("/refresh", post(user_refresh))
+        .route("/logout", post(user_logout))
+        .route("/password", put(change_password))
+        .route("/password/forgot", post(forgot_password))
+        .route("/password/reset", post(reset_password))
+        .route("/invitation", get(list_invitations).post(create_invitation))
+        .route("/invitation/:id", delete(revoke_invitation))
         .route("/details/:id", get(user_details))
         .route("/update/:id", put(update_user));
 
@@ -51,12 +82,32 @@ async fn main() -> Result<()> {
         .route("/register", post(company_register))
         .route("/update/:id", put(update_company));
 
+    let platform_router =
+        Router::new().route("/company/:id/subscription", put(update_subscription));
+
+    let role_router = Router::new()
+        .route("/", get(list_roles).post(create_role))
+        .route("/:id", get(get_role).put(update_role).delete(delete_role))
+        .route("/name/:name", get(get_role_by_name))
+        .route("/:id/user/:user_id", put(assign_role));
+
+    let custom_field_router = Router::new()
+        .route("/", get(list_custom_fields).post(create_custom_field))
+        .route("/:id", put(update_custom_field).delete(delete_custom_field));
+
     let asset_router = Router::new()
-        .route("/", post(create_asset))
+        .route("/", get(list_assets).post(create_asset))
         .route(
             "/:id",
             get(get_asset).put(update_asset).delete(delete_asset),
         )
+        .route("/code/:code", get(get_asset_by_code))
+        .route("/tree", get(get_asset_tree))
+        .route("/import", post(import_assets))
+        .route("/export", get(export_assets))
+        .route("/:id/move", put(move_asset))
+        .route("/:id/where_used", get(get_asset_where_used))
+        .route("/:id/history", get(get_asset_history))
         .route("/location", post(create_location))
         .route(
             "/location/:id",
@@ -65,7 +116,13 @@ async fn main() -> Result<()> {
                 .delete(delete_location),
         )
         .route("/location/nested", get(get_nested_location))
+        .route("/location/tree", get(get_location_tree))
+        .route("/location/import", post(import_locations))
+        .route("/location/export", get(export_locations))
+        .route("/location/:id/move", put(move_location))
+        .route("/location/code/:code", get(get_location_by_code))
         .route("/status", post(create_status))
+        .route("/status/code/:code", get(get_status_by_code))
         .route(
             "/status/:id",
             get(get_status)
@@ -74,24 +131,113 @@ async fn main() -> Result<()> {
         );
 
     let mr_router = Router::new()
-        .route("/", post(create_mr))
-        .route("/:id", get(get_mr))
-        .route("/status", post(create_mr_status))
-        .route("/status/:id", post(get_mr_status))
-        .route("/priority", post(create_mr_priority))
-        .route("/priority/:id", post(get_mr_priority))
-        .route("/category", post(create_mr_category))
-        .route("/category/:id", post(get_mr_category))
-        .route("/failure_impact", post(create_mr_failure_impact))
-        .route("/failure_impact/:id", post(get_mr_failure_impact))
-        .route("/failure_mode", post(create_mr_failure_mode))
-        .route("/failure_mode/:id", post(get_mr_failure_mode));
+        .route("/", get(list_mrs).post(create_mr))
+        .route("/export", get(export_mrs))
+        .route("/:id", get(get_mr).put(update_mr).delete(delete_mr))
+        .route("/:id/acknowledge", post(acknowledge_mr))
+        .route("/:id/resolve", post(resolve_mr))
+        .route("/:id/close", post(close_mr))
+        .route("/:id/cancel", post(cancel_mr))
+        .route("/:id/reopen", post(reopen_mr))
+        .route("/status", get(mr_status::list).post(mr_status::create))
+        .route(
+            "/status/:id",
+            get(mr_status::get)
+                .put(mr_status::update)
+                .delete(mr_status::delete),
+        )
+        .route("/status/code/:code", get(mr_status::get_by_code))
+        .route(
+            "/transition",
+            get(list_mr_transitions).post(create_mr_transition),
+        )
+        .route(
+            "/transition/:id",
+            put(update_mr_transition).delete(delete_mr_transition),
+        )
+        .route(
+            "/priority",
+            get(mr_priority::list).post(mr_priority::create),
+        )
+        .route(
+            "/priority/:id",
+            get(mr_priority::get)
+                .put(mr_priority::update)
+                .delete(mr_priority::delete),
+        )
+        .route("/priority/code/:code", get(mr_priority::get_by_code))
+        .route(
+            "/category",
+            get(mr_category::list).post(mr_category::create),
+        )
+        .route(
+            "/category/:id",
+            get(mr_category::get)
+                .put(mr_category::update)
+                .delete(mr_category::delete),
+        )
+        .route("/category/code/:code", get(mr_category::get_by_code))
+        .route(
+            "/failure_impact",
+            get(mr_failure_impact::list).post(mr_failure_impact::create),
+        )
+        .route(
+            "/failure_impact/:id",
+            get(mr_failure_impact::get)
+                .put(mr_failure_impact::update)
+                .delete(mr_failure_impact::delete),
+        )
+        .route(
+            "/failure_impact/code/:code",
+            get(mr_failure_impact::get_by_code),
+        )
+        .route(
+            "/failure_mode",
+            get(mr_failure_mode::list).post(mr_failure_mode::create),
+        )
+        .route(
+            "/failure_mode/:id",
+            get(mr_failure_mode::get)
+                .put(mr_failure_mode::update)
+                .delete(mr_failure_mode::delete),
+        )
+        .route(
+            "/failure_mode/code/:code",
+            get(mr_failure_mode::get_by_code),
+        );
+
+    let wo_router = Router::new()
+        .route("/", get(list_wos).post(create_wo))
+        .route("/:id", get(get_wo).put(update_wo).delete(delete_wo))
+        .route("/:id/assignees", put(set_wo_assignees))
+        .route("/:id/start", post(start_wo))
+        .route("/:id/hold", post(hold_wo))
+        .route("/:id/complete", post(complete_wo))
+        .route("/:id/cancel", post(cancel_wo))
+        .route("/:id/task", post(add_wo_task))
+        .route(
+            "/:id/task/:task_id",
+            put(update_wo_task).delete(delete_wo_task),
+        )
+        .route("/:id/labour", post(add_wo_labour))
+        .route("/:id/labour/:labour_id", delete(delete_wo_labour))
+        .route("/:id/part", post(add_wo_part))
+        .route("/:id/part/:part_id", delete(delete_wo_part));
+
+    let trash_router = Router::new()
+        .route("/", get(list_trash).delete(purge_trash))
+        .route("/:kind/:id/restore", post(restore_trash));
 
     let api_routes = Router::new()
         .nest("/user", user_router)
         .nest("/company", company_router)
+        .nest("/role", role_router)
+        .nest("/platform", platform_router)
+        .nest("/custom_field", custom_field_router)
         .nest("/asset", asset_router)
-        .nest("/mr", mr_router);
+        .nest("/mr", mr_router)
+        .nest("/wo", wo_router)
+        .nest("/trash", trash_router);
 
     let router = Router::new().nest("/api", api_routes).layer(cors);
     tracing::debug!("{:?}", router);
@@ -0,0 +1,238 @@
+/// Handlers of the code/name lookup tables of maintenance requests, one
+/// module per lookup with the same list, get, create, update and delete
+/// endpoints. `$fk` is the field of the mr referencing the lookup, `$other`
+/// are further models whose `$other_fk` fields keep it from being deleted.
+/// `$extra` are the lookup's own optional fields, `#[fixed_while_used]` ones
+/// can not change while an mr references the lookup.
+macro_rules! mr_lookup {
+    (@fixed_while_used $tenant:ident, $model:ident, $id:ident, $fk:ident, $label:literal, $extra:ident, $value:expr) => {
+        if let Some(value) = &$value {
+            let used = $tenant
+                .maintainance_request()
+                .count(vec![db::maintainance_request::$fk::equals($id)])
+                .exec()
+                .await?;
+            if used > 0 && value != &$tenant.$model().get($id).await?.$extra {
+                return Err(AppError::Custom {
+                    status_code: 409,
+                    error: format!(
+                        "{} of the {} can not change while {} mrs use it",
+                        stringify!($extra),
+                        $label,
+                        used
+                    ),
+                });
+            }
+        }
+    };
+    ($(
+        $model:ident ($label:literal, $fk:ident $(, $other:ident($($other_fk:ident),+) as $records:literal)*) {
+            $code:ident,
+            $name:ident
+            $(, $(#[$fixed:ident])? $extra:ident: $ty:ty)* $(,)?
+        }
+    )*) => {
+        $(
+            pub mod $model {
+                use axum::debug_handler;
+                use axum::extract::Path;
+                use axum::Json;
+                use prisma_client_rust::Direction;
+                use serde::{Deserialize, Serialize};
+
+                use crate::db;
+                use crate::errors::{AppError, AppResult, CommonResponse};
+                use crate::repo::check_unused;
+                use crate::utils::JwtClaims;
+                use crate::DB;
+
+                #[derive(Debug, Serialize, Deserialize)]
+                pub struct CreateInfo {
+                    pub $code: String,
+                    pub $name: String,
+                    $(pub $extra: Option<$ty>,)*
+                }
+
+                db::$model::partial!(
+                    UpdateInfo {
+                        $code
+                        $name
+                        $($extra)*
+                    }
+                );
+
+                fn not_found() -> AppError {
+                    AppError::Custom {
+                        status_code: 404,
+                        error: format!("{} not found", $label),
+                    }
+                }
+
+                #[debug_handler]
+                pub async fn list(
+                    c: JwtClaims,
+                ) -> AppResult<Json<CommonResponse<Vec<db::$model::Data>>>> {
+                    c.check_module_privilige(db::Module::MaintainanceRequest, db::PrivilegeType::View)
+                        .await?;
+                    let client = DB.get().unwrap();
+                    CommonResponse::json_data(
+                        c.tenant(client)
+                            .$model()
+                            .find_many(vec![])
+                            .order_by(db::$model::$code::order(Direction::Asc))
+                            .exec()
+                            .await?,
+                    )
+                }
+
+                #[debug_handler]
+                pub async fn get(
+                    Path(id): Path<i32>,
+                    c: JwtClaims,
+                ) -> AppResult<Json<CommonResponse<db::$model::Data>>> {
+                    c.check_module_privilige(db::Module::MaintainanceRequest, db::PrivilegeType::View)
+                        .await?;
+                    let client = DB.get().unwrap();
+                    match c.tenant(client).$model().find_by_id(id).exec().await? {
+                        Some(s) => CommonResponse::json_data(s),
+                        _ => Err(not_found()),
+                    }
+                }
+
+                #[debug_handler]
+                pub async fn get_by_code(
+                    Path(code): Path<String>,
+                    c: JwtClaims,
+                ) -> AppResult<Json<CommonResponse<db::$model::Data>>> {
+                    c.check_module_privilige(db::Module::MaintainanceRequest, db::PrivilegeType::View)
+                        .await?;
+                    let client = DB.get().unwrap();
+                    let found = c
+                        .tenant(client)
+                        .$model()
+                        .find_first(vec![db::$model::$code::equals(code)])
+                        .exec()
+                        .await?;
+                    match found {
+                        Some(s) => CommonResponse::json_data(s),
+                        _ => Err(not_found()),
+                    }
+                }
+
+                #[debug_handler]
+                pub async fn create(
+                    c: JwtClaims,
+                    Json(payload): Json<CreateInfo>,
+                ) -> AppResult<Json<CommonResponse<db::$model::Data>>> {
+                    c.check_module_privilige(db::Module::MaintainanceRequest, db::PrivilegeType::Edit)
+                        .await?;
+                    let client = DB.get().unwrap();
+                    #[allow(unused_mut)]
+                    let mut params = vec![];
+                    $(
+                        if let Some(value) = payload.$extra {
+                            params.push(db::$model::$extra::set(value));
+                        }
+                    )*
+                    CommonResponse::json_data(
+                        c.tenant(client)
+                            .$model()
+                            .create(|records, company| {
+                                records.create(company, payload.$code, payload.$name, params)
+                            })
+                            .exec()
+                            .await?,
+                    )
+                }
+
+                #[debug_handler]
+                pub async fn update(
+                    Path(id): Path<i32>,
+                    c: JwtClaims,
+                    Json(payload): Json<UpdateInfo>,
+                ) -> AppResult<Json<CommonResponse<db::$model::Data>>> {
+                    c.check_module_privilige(db::Module::MaintainanceRequest, db::PrivilegeType::Edit)
+                        .await?;
+                    let client = DB.get().unwrap();
+                    let tenant = c.tenant(client);
+                    $($(
+                        mr_lookup!(@$fixed tenant, $model, id, $fk, $label, $extra, payload.$extra);
+                    )?)*
+                    CommonResponse::json_data(
+                        tenant
+                            .$model()
+                            .update(id, payload.to_params())
+                            .await?
+                            .exec()
+                            .await?,
+                    )
+                }
+
+                /// Lookups still used by a live mr can not be deleted.
+                #[debug_handler]
+                pub async fn delete(
+                    Path(id): Path<i32>,
+                    c: JwtClaims,
+                ) -> AppResult<Json<CommonResponse<db::$model::Data>>> {
+                    c.check_module_privilige(db::Module::MaintainanceRequest, db::PrivilegeType::Edit)
+                        .await?;
+                    let client = DB.get().unwrap();
+                    let tenant = c.tenant(client);
+                    tenant.$model().ensure(id).await?;
+                    check_unused(
+                        $label,
+                        vec![
+                            (
+                                "mrs",
+                                tenant
+                                    .maintainance_request()
+                                    .count(vec![db::maintainance_request::$fk::equals(id)])
+                                    .exec()
+                                    .await?,
+                            ),
+                            $((
+                                $records,
+                                0 $(+ tenant
+                                    .$other()
+                                    .count(vec![db::$other::$other_fk::equals(id)])
+                                    .exec()
+                                    .await?)+,
+                            ),)*
+                        ],
+                    )?;
+                    CommonResponse::json_data(tenant.$model().soft_delete(id).await?.exec().await?)
+                }
+            }
+        )*
+    };
+}
+
+mr_lookup! {
+    mr_status (
+        "mr status",
+        mr_status_id,
+        mr_status_transition(from_status_id, to_status_id) as "transitions"
+    ) {
+        status_code,
+        status_name,
+        // the step of the workflow the status stands for, open when absent,
+        // the state of the mrs in the status follows it
+        #[fixed_while_used] status_type: db::MrStatusType,
+    }
+    mr_category ("mr category", mr_category_id) {
+        category_code,
+        category_name
+    }
+    mr_priority ("mr priority", mr_priority_id) {
+        priority_code,
+        priority_name
+    }
+    mr_failure_impact ("mr failure impact", mr_failure_impact_id) {
+        failure_impact_code,
+        failure_impact_name
+    }
+    mr_failure_mode ("mr failure mode", mr_failure_mode_id) {
+        failure_mode_code,
+        failure_mode_name
+    }
+}
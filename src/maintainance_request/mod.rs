@@ -1,12 +1,25 @@
+use crate::assets::tree::{asset_tree, location_tree};
+use crate::db;
 use crate::errors::{AppError, AppResult, CommonResponse};
+use crate::repo::{check_references, check_unused, page_size, Page, SortOrder, Tenant};
 use crate::utils::JwtClaims;
 use crate::DB;
-use crate::{cmp_company_id, db};
 
 use axum::debug_handler;
-use axum::extract::Path;
+use axum::extract::{Path, Query};
 use axum::Json;
+use chrono::{DateTime, FixedOffset, Utc};
+use prisma_client_rust::Direction;
 use serde::{Deserialize, Serialize};
+use tracing::info;
+
+mod lookup;
+pub use lookup::{mr_category, mr_failure_impact, mr_failure_mode, mr_priority, mr_status};
+pub mod workflow;
+use workflow::check_transition;
+pub use workflow::{
+    create_mr_transition, delete_mr_transition, list_mr_transitions, update_mr_transition,
+};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CreateMrInfo {
@@ -19,6 +32,8 @@ pub struct CreateMrInfo {
     mr_failure_mode_id: i32,
     mr_error_code: String,
     mr_description: String,
+    /// the user working on the request
+    assignee_id: Option<i32>,
 }
 
 #[debug_handler]
@@ -29,43 +44,162 @@ pub async fn create_mr(
     c.check_module_privilige(db::Module::MaintainanceRequest, db::PrivilegeType::Edit)
         .await?;
     let client = DB.get().unwrap();
-    CommonResponse::json_data(
-        client
-            .maintainance_request()
-            .create(
-                db::company::id::equals(c.company_id),
-                db::asset::id::equals(payload.asset_id),
-                payload.mr_name,
-                db::user::id::equals(c.user_id),
-                db::mr_status::id::equals(payload.mr_status_id),
-                db::mr_category::id::equals(payload.mr_category_id),
-                db::mr_priority::id::equals(payload.mr_priority_id),
-                db::mr_failure_impact::id::equals(payload.mr_failure_impact_id),
-                db::mr_failure_mode::id::equals(payload.mr_failure_mode_id),
-                payload.mr_error_code,
-                payload.mr_description,
-                vec![],
-            )
-            .exec()
-            .await?,
-    )
+    let tenant = c.tenant(client);
+    check_references(vec![
+        ("asset_id", tenant.asset().owns(payload.asset_id).await?),
+        (
+            "mr_status_id",
+            tenant.mr_status().owns(payload.mr_status_id).await?,
+        ),
+        (
+            "mr_category_id",
+            tenant.mr_category().owns(payload.mr_category_id).await?,
+        ),
+        (
+            "mr_priority_id",
+            tenant.mr_priority().owns(payload.mr_priority_id).await?,
+        ),
+        (
+            "mr_failure_impact_id",
+            tenant
+                .mr_failure_impact()
+                .owns(payload.mr_failure_impact_id)
+                .await?,
+        ),
+        (
+            "mr_failure_mode_id",
+            tenant
+                .mr_failure_mode()
+                .owns(payload.mr_failure_mode_id)
+                .await?,
+        ),
+        (
+            "assignee_id",
+            tenant.user().owns_opt(payload.assignee_id).await?,
+        ),
+    ])?;
+    let status_type = tenant
+        .mr_status()
+        .get(payload.mr_status_id)
+        .await?
+        .status_type;
+    let (company_id, user_id) = (c.company_id, c.user_id);
+    let mr = client
+        ._transaction()
+        .run(|client| async move {
+            let mr = Tenant::new(&client, company_id)
+                .maintainance_request()
+                .create(|mrs, company| {
+                    mrs.create(
+                        company,
+                        db::asset::id::equals(payload.asset_id),
+                        payload.mr_name,
+                        db::user::id::equals(user_id),
+                        db::mr_status::id::equals(payload.mr_status_id),
+                        db::mr_category::id::equals(payload.mr_category_id),
+                        db::mr_priority::id::equals(payload.mr_priority_id),
+                        db::mr_failure_impact::id::equals(payload.mr_failure_impact_id),
+                        db::mr_failure_mode::id::equals(payload.mr_failure_mode_id),
+                        payload.mr_error_code,
+                        payload.mr_description,
+                        vec![db::maintainance_request::assignee_id::set(
+                            payload.assignee_id,
+                        )],
+                    )
+                })
+                .exec()
+                .await?;
+            // a new mr is open, the status it starts in may have moved it on
+            match MrAction::for_status_type(status_type, mr.mr_state) {
+                Some(action) => Ok(client
+                    .maintainance_request()
+                    .update(
+                        db::maintainance_request::id::equals(mr.id),
+                        action.params(&mr)?,
+                    )
+                    .exec()
+                    .await?),
+                None => Ok::<_, AppError>(mr),
+            }
+        })
+        .await?;
+    CommonResponse::json_data(mr)
+}
+
+db::maintainance_request::select! {
+    mr_out {
+        id
+        mr_name
+        mr_state
+        mr_error_code
+        mr_description
+        created_at
+        updated_at
+        opened_at
+        acknowledged_at
+        resolved_at
+        closed_at
+        asset: select {
+            id
+            asset_code
+            asset_name
+        }
+        mr_status: select {
+            id
+            status_code
+            status_name
+            status_type
+        }
+        mr_category: select {
+            id
+            category_code
+            category_name
+        }
+        mr_priority: select {
+            id
+            priority_code
+            priority_name
+        }
+        mr_failure_impact: select {
+            id
+            failure_impact_code
+            failure_impact_name
+        }
+        mr_failure_mode: select {
+            id
+            failure_mode_code
+            failure_mode_name
+        }
+        mr_reporter: select {
+            id
+            username
+            nickname
+        }
+        assignee: select {
+            id
+            username
+            nickname
+        }
+    }
 }
 
 #[debug_handler]
 pub async fn get_mr(
     Path(id): Path<i32>,
     c: JwtClaims,
-) -> AppResult<Json<CommonResponse<db::maintainance_request::Data>>> {
+) -> AppResult<Json<CommonResponse<mr_out::Data>>> {
     c.check_module_privilige(db::Module::MaintainanceRequest, db::PrivilegeType::View)
         .await?;
     let client = DB.get().unwrap();
-    let a = client
+    let a = c
+        .tenant(client)
         .maintainance_request()
-        .find_unique(db::maintainance_request::id::equals(id))
+        .find_by_id(id)
+        .select(mr_out::select())
         .exec()
         .await?;
     match a {
-        Some(s) => cmp_company_id!(s, c),
+        Some(s) => CommonResponse::json_data(s),
         _ => Err(AppError::Custom {
             status_code: 404,
             error: "mr not found".to_string(),
@@ -73,11 +207,180 @@ pub async fn get_mr(
     }
 }
 
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MrSort {
+    #[default]
+    CreatedAt,
+    UpdatedAt,
+    MrName,
+}
+
+/// Filters of maintenance request listings.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MrListQuery {
+    pub cursor: Option<i32>,
+    pub limit: Option<i64>,
+    #[serde(default)]
+    pub sort: MrSort,
+    #[serde(default)]
+    pub order: SortOrder,
+    pub asset_id: Option<i32>,
+    /// also matches the components of `asset_id` and theirs
+    #[serde(default)]
+    pub include_components: bool,
+    /// matches requests on assets in the location and all of its descendants
+    pub location_id: Option<i32>,
+    pub state: Option<db::MrState>,
+    pub status_id: Option<i32>,
+    pub category_id: Option<i32>,
+    pub priority_id: Option<i32>,
+    pub failure_impact_id: Option<i32>,
+    pub failure_mode_id: Option<i32>,
+    /// the reporter
+    pub user_id: Option<i32>,
+    pub assignee_id: Option<i32>,
+    pub created_from: Option<DateTime<FixedOffset>>,
+    pub created_to: Option<DateTime<FixedOffset>>,
+    pub resolved_from: Option<DateTime<FixedOffset>>,
+    pub resolved_to: Option<DateTime<FixedOffset>>,
+}
+
+/// A maintenance request listing query with the asset and location subtrees
+/// resolved, shared by the listing and the export.
+pub struct MrFilter {
+    pub query: MrListQuery,
+    assets: Option<Vec<i32>>,
+    locations: Option<Vec<i32>>,
+}
+
+impl MrFilter {
+    pub async fn resolve(
+        client: &db::PrismaClient,
+        company_id: i32,
+        query: MrListQuery,
+    ) -> AppResult<Self> {
+        let tenant = Tenant::new(client, company_id);
+        let assets = match query.asset_id {
+            Some(id) if query.include_components => Some(asset_tree(tenant).await?.descendants(id)),
+            Some(id) => Some(vec![id]),
+            None => None,
+        };
+        let locations = match query.location_id {
+            Some(id) => Some(location_tree(tenant).await?.descendants(id)),
+            None => None,
+        };
+        Ok(Self {
+            query,
+            assets,
+            locations,
+        })
+    }
+
+    pub fn params(&self) -> Vec<db::maintainance_request::WhereParam> {
+        use db::maintainance_request as mr;
+        let q = &self.query;
+        let mut filters = vec![];
+        if let Some(ids) = &self.assets {
+            filters.push(mr::asset_id::in_vec(ids.clone()));
+        }
+        if let Some(ids) = &self.locations {
+            filters.push(mr::asset::is(vec![db::asset::asset_location_id::in_vec(
+                ids.clone(),
+            )]));
+        }
+        if let Some(state) = q.state {
+            filters.push(mr::mr_state::equals(state));
+        }
+        if let Some(id) = q.status_id {
+            filters.push(mr::mr_status_id::equals(id));
+        }
+        if let Some(id) = q.category_id {
+            filters.push(mr::mr_category_id::equals(id));
+        }
+        if let Some(id) = q.priority_id {
+            filters.push(mr::mr_priority_id::equals(id));
+        }
+        if let Some(id) = q.failure_impact_id {
+            filters.push(mr::mr_failure_impact_id::equals(id));
+        }
+        if let Some(id) = q.failure_mode_id {
+            filters.push(mr::mr_failure_mode_id::equals(id));
+        }
+        if let Some(id) = q.user_id {
+            filters.push(mr::user_id::equals(id));
+        }
+        if let Some(id) = q.assignee_id {
+            filters.push(mr::assignee_id::equals(Some(id)));
+        }
+        if let Some(from) = q.created_from {
+            filters.push(mr::created_at::gte(from));
+        }
+        if let Some(to) = q.created_to {
+            filters.push(mr::created_at::lt(to));
+        }
+        if let Some(from) = q.resolved_from {
+            filters.push(mr::resolved_at::gte(from));
+        }
+        if let Some(to) = q.resolved_to {
+            filters.push(mr::resolved_at::lt(to));
+        }
+        filters
+    }
+
+    /// The requested sort, the id breaks ties so that cursors are stable.
+    pub fn order_by(&self) -> Vec<db::maintainance_request::OrderByParam> {
+        use db::maintainance_request as mr;
+        let direction = Direction::from(self.query.order);
+        vec![
+            match self.query.sort {
+                MrSort::CreatedAt => mr::created_at::order(direction),
+                MrSort::UpdatedAt => mr::updated_at::order(direction),
+                MrSort::MrName => mr::mr_name::order(direction),
+            },
+            mr::id::order(direction),
+        ]
+    }
+}
+
+#[debug_handler]
+pub async fn list_mrs(
+    c: JwtClaims,
+    Query(q): Query<MrListQuery>,
+) -> AppResult<Json<CommonResponse<Page<mr_out::Data>>>> {
+    c.check_module_privilige(db::Module::MaintainanceRequest, db::PrivilegeType::View)
+        .await?;
+    let client = DB.get().unwrap();
+    let tenant = c.tenant(client);
+    let size = page_size(q.limit);
+    let cursor = q.cursor;
+    let filter = MrFilter::resolve(client, c.company_id, q).await?;
+    let total = tenant
+        .maintainance_request()
+        .count(filter.params())
+        .exec()
+        .await?;
+    let mut query = tenant
+        .maintainance_request()
+        .find_many(filter.params())
+        .take(size + 1);
+    for order in filter.order_by() {
+        query = query.order_by(order);
+    }
+    if let Some(cursor) = cursor {
+        query = query
+            .cursor(db::maintainance_request::id::equals(cursor))
+            .skip(1);
+    }
+    let mrs = query.select(mr_out::select()).exec().await?;
+    CommonResponse::json_data(Page::new(mrs, total, size, |m| m.id))
+}
+
+// the reporter (`user_id`) can not be changed after the mr is created
 db::maintainance_request::partial!(
     UpdateMRInfo {
         asset_id
         mr_name
-        user_id
         mr_status_id
         mr_category_id
         mr_priority_id
@@ -85,235 +388,461 @@ db::maintainance_request::partial!(
         mr_failure_mode_id
         mr_error_code
         mr_description
+        assignee_id
     }
 );
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct CreateMrStatusInfo {
-    pub status_code: String,
-    pub status_name: String,
+/// Closed and cancelled mrs have to be reopened before they can be changed.
+pub(crate) fn check_open(mr: &db::maintainance_request::Data) -> AppResult<()> {
+    match mr.mr_state {
+        db::MrState::Closed | db::MrState::Cancelled => Err(AppError::Custom {
+            status_code: 409,
+            error: format!("mr is {:?} and has to be reopened first", mr.mr_state).to_lowercase(),
+        }),
+        _ => Ok(()),
+    }
 }
 
 #[debug_handler]
-pub async fn create_mr_status(
+pub async fn update_mr(
+    Path(id): Path<i32>,
     c: JwtClaims,
-    Json(payload): Json<CreateMrStatusInfo>,
-) -> AppResult<Json<CommonResponse<db::mr_status::Data>>> {
+    Json(mut payload): Json<UpdateMRInfo>,
+) -> AppResult<Json<CommonResponse<db::maintainance_request::Data>>> {
+    c.check_module_privilige(db::Module::MaintainanceRequest, db::PrivilegeType::Edit)
+        .await?;
     let client = DB.get().unwrap();
-    CommonResponse::json_data(
-        client
-            .mr_status()
-            .create(
-                db::company::id::equals(c.company_id),
-                payload.status_code,
-                payload.status_name,
-                vec![],
-            )
-            .exec()
-            .await?,
-    )
+    let tenant = c.tenant(client);
+    let mr = tenant.maintainance_request().get(id).await?;
+    check_references(vec![
+        ("asset_id", tenant.asset().owns_opt(payload.asset_id).await?),
+        (
+            "mr_status_id",
+            tenant.mr_status().owns_opt(payload.mr_status_id).await?,
+        ),
+        (
+            "mr_category_id",
+            tenant
+                .mr_category()
+                .owns_opt(payload.mr_category_id)
+                .await?,
+        ),
+        (
+            "mr_priority_id",
+            tenant
+                .mr_priority()
+                .owns_opt(payload.mr_priority_id)
+                .await?,
+        ),
+        (
+            "mr_failure_impact_id",
+            tenant
+                .mr_failure_impact()
+                .owns_opt(payload.mr_failure_impact_id)
+                .await?,
+        ),
+        (
+            "mr_failure_mode_id",
+            tenant
+                .mr_failure_mode()
+                .owns_opt(payload.mr_failure_mode_id)
+                .await?,
+        ),
+        (
+            "assignee_id",
+            tenant
+                .user()
+                .owns_opt(payload.assignee_id.flatten())
+                .await?,
+        ),
+    ])?;
+    let status_id = payload.mr_status_id.take();
+    let status = match status_id.filter(|s| *s != mr.mr_status_id) {
+        Some(status_id) => Some(tenant.mr_status().get(status_id).await?),
+        None => None,
+    };
+    // moving a closed mr to an open status reopens it
+    if !status
+        .as_ref()
+        .map_or(false, |s| s.status_type == db::MrStatusType::Open)
+    {
+        check_open(&mr)?;
+    }
+    let mut params = match &status {
+        Some(status) => move_to_status(tenant, c.role_id, &mr, status, None).await?,
+        None => vec![],
+    };
+    params.extend(payload.to_params());
+    CommonResponse::json_data(update_unmoved(tenant, &mr, params).await?)
 }
 
-#[debug_handler]
-pub async fn get_mr_status(
-    Path(id): Path<i32>,
-    c: JwtClaims,
-) -> AppResult<Json<CommonResponse<db::mr_status::Data>>> {
-    let client = DB.get().unwrap();
-    let a = client
-        .mr_status()
-        .find_unique(db::mr_status::id::equals(id))
-        .exec()
-        .await?;
-    match a {
-        Some(s) => cmp_company_id!(s, c),
-        _ => Err(AppError::Custom {
-            status_code: 404,
-            error: "mr not found".to_string(),
-        }),
+/// Steps of the mr lifecycle, each records the time it was taken.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum MrAction {
+    Acknowledge,
+    Resolve,
+    Close,
+    Cancel,
+    Reopen,
+}
+
+impl MrAction {
+    /// The step moving an mr in the state to a status of the type takes, none
+    /// when the state already fits the type.
+    fn for_status_type(status_type: db::MrStatusType, state: db::MrState) -> Option<Self> {
+        use db::MrState as S;
+        use db::MrStatusType as T;
+        match (status_type, state) {
+            (T::Open, S::Open)
+            | (T::InProgress | T::OnHold, S::Acknowledged)
+            | (T::Resolved, S::Resolved)
+            | (T::Closed, S::Closed | S::Cancelled) => None,
+            (T::Open, _) => Some(MrAction::Reopen),
+            (T::InProgress | T::OnHold, _) => Some(MrAction::Acknowledge),
+            (T::Resolved, _) => Some(MrAction::Resolve),
+            (T::Closed, _) => Some(MrAction::Close),
+        }
+    }
+
+    /// The types of the statuses the step may move the mr to.
+    fn status_types(self) -> &'static [db::MrStatusType] {
+        use db::MrStatusType::*;
+        match self {
+            MrAction::Acknowledge => &[InProgress, OnHold],
+            MrAction::Resolve => &[Resolved],
+            MrAction::Close | MrAction::Cancel => &[Closed],
+            MrAction::Reopen => &[Open],
+        }
+    }
+
+    fn done(self) -> &'static str {
+        match self {
+            MrAction::Acknowledge => "acknowledged",
+            MrAction::Resolve => "resolved",
+            MrAction::Close => "closed",
+            MrAction::Cancel => "cancelled",
+            MrAction::Reopen => "reopened",
+        }
+    }
+
+    /// The changes the action makes to the mr, fails with a 409 when it can
+    /// not be taken from the current state.
+    fn params(
+        self,
+        mr: &db::maintainance_request::Data,
+    ) -> AppResult<Vec<db::maintainance_request::SetParam>> {
+        use db::maintainance_request as m;
+        use db::MrState::*;
+        let now: DateTime<FixedOffset> = Utc::now().into();
+        let params = match (self, mr.mr_state) {
+            (MrAction::Acknowledge, Open) => vec![
+                m::mr_state::set(Acknowledged),
+                m::acknowledged_at::set(Some(now)),
+            ],
+            (MrAction::Resolve, Open | Acknowledged) => vec![
+                m::mr_state::set(Resolved),
+                m::acknowledged_at::set(mr.acknowledged_at.or(Some(now))),
+                m::resolved_at::set(Some(now)),
+            ],
+            (MrAction::Close, Open | Acknowledged | Resolved) => {
+                vec![m::mr_state::set(Closed), m::closed_at::set(Some(now))]
+            }
+            (MrAction::Cancel, Open | Acknowledged) => {
+                vec![m::mr_state::set(Cancelled), m::closed_at::set(Some(now))]
+            }
+            (MrAction::Reopen, Resolved | Closed | Cancelled) => vec![
+                m::mr_state::set(Open),
+                m::opened_at::set(now),
+                m::acknowledged_at::set(None),
+                m::resolved_at::set(None),
+                m::closed_at::set(None),
+            ],
+            (action, state) => {
+                return Err(AppError::Custom {
+                    status_code: 409,
+                    error: format!("mr is {:?} and can not be {}", state, action.done())
+                        .to_lowercase(),
+                })
+            }
+        };
+        Ok(params)
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct CreateMrCategoryInfo {
-    pub category_code: String,
-    pub category_name: String,
+/// The changes moving the mr to the status makes. The status and the
+/// lifecycle state of an mr only ever change together through here, the
+/// state follows the type of the status unless `action` asks for a step.
+pub(crate) async fn move_to_status(
+    tenant: Tenant<'_>,
+    role_id: i32,
+    mr: &db::maintainance_request::Data,
+    status: &db::mr_status::Data,
+    action: Option<MrAction>,
+) -> AppResult<Vec<db::maintainance_request::SetParam>> {
+    let action = match action {
+        Some(a) if !a.status_types().contains(&status.status_type) => {
+            return Err(AppError::InvalidFields(vec!["mr_status_id".to_string()]))
+        }
+        Some(a) => Some(a),
+        None => MrAction::for_status_type(status.status_type, mr.mr_state),
+    };
+    check_transition(tenant, role_id, mr.mr_status_id, status.id).await?;
+    let mut params = match action {
+        Some(a) => a.params(mr)?,
+        None => vec![],
+    };
+    params.push(db::maintainance_request::mr_status_id::set(status.id));
+    Ok(params)
 }
 
-#[debug_handler]
-pub async fn create_mr_category(
-    c: JwtClaims,
-    Json(payload): Json<CreateMrCategoryInfo>,
-) -> AppResult<Json<CommonResponse<db::mr_category::Data>>> {
-    let client = DB.get().unwrap();
-    CommonResponse::json_data(
-        client
-            .mr_category()
-            .create(
-                db::company::id::equals(c.company_id),
-                payload.category_code,
-                payload.category_name,
-                vec![],
-            )
-            .exec()
-            .await?,
-    )
+/// The status a step moves the mr to when the caller does not pick one, its
+/// current status if the type fits the step or else the first one of the
+/// company that does.
+pub(crate) async fn status_for(
+    tenant: Tenant<'_>,
+    mr: &db::maintainance_request::Data,
+    action: MrAction,
+) -> AppResult<Option<db::mr_status::Data>> {
+    let mut statuses = tenant
+        .mr_status()
+        .find_many(vec![db::mr_status::status_type::in_vec(
+            action.status_types().to_vec(),
+        )])
+        .order_by(db::mr_status::id::order(Direction::Asc))
+        .exec()
+        .await?;
+    let current = statuses.iter().position(|s| s.id == mr.mr_status_id);
+    Ok(match current {
+        Some(i) => Some(statuses.swap_remove(i)),
+        None => statuses.into_iter().next(),
+    })
 }
 
-#[debug_handler]
-pub async fn get_mr_category(
-    Path(id): Path<i32>,
-    c: JwtClaims,
-) -> AppResult<Json<CommonResponse<db::mr_category::Data>>> {
-    let client = DB.get().unwrap();
-    let a = client
-        .mr_category()
-        .find_unique(db::mr_category::id::equals(id))
+/// The changes taking the step makes when another change calls for it, such
+/// as work orders being done. None when the mr can not take it from where it
+/// is, or the workflow wants it to go through other statuses first, which is
+/// left to the users.
+pub(crate) async fn follow_up(
+    tenant: Tenant<'_>,
+    role_id: i32,
+    mr: &db::maintainance_request::Data,
+    action: MrAction,
+) -> AppResult<Option<Vec<db::maintainance_request::SetParam>>> {
+    let status = match status_for(tenant, mr, action).await? {
+        Some(status) => status,
+        None => return Ok(None),
+    };
+    match move_to_status(tenant, role_id, mr, &status, Some(action)).await {
+        Ok(params) => Ok(Some(params)),
+        Err(AppError::Custom {
+            status_code: 403 | 409,
+            error,
+        }) => {
+            info!("mr {} is not {}: {}", mr.id, action.done(), error);
+            Ok(None)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Apply the changes unless another request moved the mr to another status
+/// or state since it was read, fails with a 409 then.
+pub(crate) async fn update_unmoved(
+    tenant: Tenant<'_>,
+    mr: &db::maintainance_request::Data,
+    params: Vec<db::maintainance_request::SetParam>,
+) -> AppResult<db::maintainance_request::Data> {
+    let updated = tenant
+        .maintainance_request()
+        .update_many(
+            vec![
+                db::maintainance_request::id::equals(mr.id),
+                db::maintainance_request::mr_status_id::equals(mr.mr_status_id),
+                db::maintainance_request::mr_state::equals(mr.mr_state),
+            ],
+            params,
+        )
         .exec()
         .await?;
-    match a {
-        Some(s) => cmp_company_id!(s, c),
-        _ => Err(AppError::Custom {
-            status_code: 404,
-            error: "mr not found".to_string(),
-        }),
+    if updated == 0 {
+        return Err(AppError::Custom {
+            status_code: 409,
+            error: "mr was changed meanwhile, try again".to_string(),
+        });
     }
+    tenant.maintainance_request().get(mr.id).await
 }
 
 #[derive(Debug, Serialize, Deserialize)]
-pub struct CreateMrPriorityInfo {
-    pub priority_code: String,
-    pub priority_name: String,
+pub struct MrStepInfo {
+    /// the status to move the mr to, picked by `status_for` when absent
+    pub mr_status_id: Option<i32>,
 }
 
-#[debug_handler]
-pub async fn create_mr_priority(
+async fn change_mr_state(
+    id: i32,
     c: JwtClaims,
-    Json(payload): Json<CreateMrPriorityInfo>,
-) -> AppResult<Json<CommonResponse<db::mr_priority::Data>>> {
+    action: MrAction,
+    payload: Option<Json<MrStepInfo>>,
+) -> AppResult<Json<CommonResponse<db::maintainance_request::Data>>> {
+    c.check_module_privilige(db::Module::MaintainanceRequest, db::PrivilegeType::Edit)
+        .await?;
     let client = DB.get().unwrap();
-    CommonResponse::json_data(
-        client
-            .mr_priority()
-            .create(
-                db::company::id::equals(c.company_id),
-                payload.priority_code,
-                payload.priority_name,
-                vec![],
-            )
+    let tenant = c.tenant(client);
+    let mr = tenant.maintainance_request().get(id).await?;
+    let status = match payload.and_then(|Json(p)| p.mr_status_id) {
+        Some(status_id) => tenant
+            .mr_status()
+            .find_by_id(status_id)
             .exec()
-            .await?,
-    )
+            .await?
+            .ok_or_else(|| AppError::InvalidReferences(vec!["mr_status_id".to_string()]))?,
+        None => status_for(tenant, &mr, action)
+            .await?
+            .ok_or_else(|| AppError::Custom {
+                status_code: 409,
+                error: format!("there is no mr status for {} mrs", action.done()),
+            })?,
+    };
+    let params = move_to_status(tenant, c.role_id, &mr, &status, Some(action)).await?;
+    let mr = update_unmoved(tenant, &mr, params).await?;
+    info!("mr {} of company {} {}", id, c.company_id, action.done());
+    CommonResponse::json_data(mr)
 }
 
 #[debug_handler]
-pub async fn get_mr_priority(
+pub async fn acknowledge_mr(
     Path(id): Path<i32>,
     c: JwtClaims,
-) -> AppResult<Json<CommonResponse<db::mr_priority::Data>>> {
-    let client = DB.get().unwrap();
-    let a = client
-        .mr_priority()
-        .find_unique(db::mr_priority::id::equals(id))
-        .exec()
-        .await?;
-    match a {
-        Some(s) => cmp_company_id!(s, c),
-        _ => Err(AppError::Custom {
-            status_code: 404,
-            error: "mr not found".to_string(),
-        }),
-    }
+    payload: Option<Json<MrStepInfo>>,
+) -> AppResult<Json<CommonResponse<db::maintainance_request::Data>>> {
+    change_mr_state(id, c, MrAction::Acknowledge, payload).await
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct CreateMrFailureImpactInfo {
-    pub failure_impact_code: String,
-    pub failure_impact_name: String,
+#[debug_handler]
+pub async fn resolve_mr(
+    Path(id): Path<i32>,
+    c: JwtClaims,
+    payload: Option<Json<MrStepInfo>>,
+) -> AppResult<Json<CommonResponse<db::maintainance_request::Data>>> {
+    change_mr_state(id, c, MrAction::Resolve, payload).await
 }
 
 #[debug_handler]
-pub async fn create_mr_failure_impact(
+pub async fn close_mr(
+    Path(id): Path<i32>,
     c: JwtClaims,
-    Json(payload): Json<CreateMrFailureImpactInfo>,
-) -> AppResult<Json<CommonResponse<db::mr_failure_impact::Data>>> {
-    let client = DB.get().unwrap();
-    CommonResponse::json_data(
-        client
-            .mr_failure_impact()
-            .create(
-                db::company::id::equals(c.company_id),
-                payload.failure_impact_code,
-                payload.failure_impact_name,
-                vec![],
-            )
-            .exec()
-            .await?,
-    )
+    payload: Option<Json<MrStepInfo>>,
+) -> AppResult<Json<CommonResponse<db::maintainance_request::Data>>> {
+    change_mr_state(id, c, MrAction::Close, payload).await
 }
 
 #[debug_handler]
-pub async fn get_mr_failure_impact(
+pub async fn cancel_mr(
     Path(id): Path<i32>,
     c: JwtClaims,
-) -> AppResult<Json<CommonResponse<db::mr_failure_impact::Data>>> {
-    let client = DB.get().unwrap();
-    let a = client
-        .mr_failure_impact()
-        .find_unique(db::mr_failure_impact::id::equals(id))
-        .exec()
-        .await?;
-    match a {
-        Some(s) => cmp_company_id!(s, c),
-        _ => Err(AppError::Custom {
-            status_code: 404,
-            error: "mr not found".to_string(),
-        }),
-    }
+    payload: Option<Json<MrStepInfo>>,
+) -> AppResult<Json<CommonResponse<db::maintainance_request::Data>>> {
+    change_mr_state(id, c, MrAction::Cancel, payload).await
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct CreateMrFailureModeInfo {
-    pub failure_mode_code: String,
-    pub failure_mode_name: String,
+#[debug_handler]
+pub async fn reopen_mr(
+    Path(id): Path<i32>,
+    c: JwtClaims,
+    payload: Option<Json<MrStepInfo>>,
+) -> AppResult<Json<CommonResponse<db::maintainance_request::Data>>> {
+    change_mr_state(id, c, MrAction::Reopen, payload).await
 }
 
 #[debug_handler]
-pub async fn create_mr_failure_mode(
+pub async fn delete_mr(
+    Path(id): Path<i32>,
     c: JwtClaims,
-    Json(payload): Json<CreateMrFailureModeInfo>,
-) -> AppResult<Json<CommonResponse<db::mr_failure_mode::Data>>> {
+) -> AppResult<Json<CommonResponse<db::maintainance_request::Data>>> {
+    c.check_module_privilige(db::Module::MaintainanceRequest, db::PrivilegeType::Edit)
+        .await?;
     let client = DB.get().unwrap();
+    let tenant = c.tenant(client);
+    tenant.maintainance_request().ensure(id).await?;
+    check_unused(
+        "mr",
+        vec![(
+            "work orders",
+            tenant
+                .work_order()
+                .count(vec![db::work_order::mr_id::equals(Some(id))])
+                .exec()
+                .await?,
+        )],
+    )?;
     CommonResponse::json_data(
-        client
-            .mr_failure_mode()
-            .create(
-                db::company::id::equals(c.company_id),
-                payload.failure_mode_code,
-                payload.failure_mode_name,
-                vec![],
-            )
+        tenant
+            .maintainance_request()
+            .soft_delete(id)
+            .await?
             .exec()
             .await?,
     )
 }
 
-#[debug_handler]
-pub async fn get_mr_failure_mode(
-    Path(id): Path<i32>,
-    c: JwtClaims,
-) -> AppResult<Json<CommonResponse<db::mr_failure_mode::Data>>> {
-    let client = DB.get().unwrap();
-    let a = client
-        .mr_failure_mode()
-        .find_unique(db::mr_failure_mode::id::equals(id))
-        .exec()
-        .await?;
-    match a {
-        Some(s) => cmp_company_id!(s, c),
-        _ => Err(AppError::Custom {
-            status_code: 404,
-            error: "mr not found".to_string(),
-        }),
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use db::MrState as S;
+    use db::MrStatusType as T;
+
+    const STATES: [S; 5] = [
+        S::Open,
+        S::Acknowledged,
+        S::Resolved,
+        S::Closed,
+        S::Cancelled,
+    ];
+    const TYPES: [T; 5] = [T::Open, T::InProgress, T::OnHold, T::Resolved, T::Closed];
+
+    #[test]
+    fn for_status_type_keeps_fitting_states() {
+        assert!(MrAction::for_status_type(T::Open, S::Open).is_none());
+        assert!(MrAction::for_status_type(T::InProgress, S::Acknowledged).is_none());
+        assert!(MrAction::for_status_type(T::OnHold, S::Acknowledged).is_none());
+        assert!(MrAction::for_status_type(T::Resolved, S::Resolved).is_none());
+        assert!(MrAction::for_status_type(T::Closed, S::Closed).is_none());
+        assert!(MrAction::for_status_type(T::Closed, S::Cancelled).is_none());
+    }
+
+    #[test]
+    fn for_status_type_steps_into_the_type() {
+        assert!(matches!(
+            MrAction::for_status_type(T::InProgress, S::Open),
+            Some(MrAction::Acknowledge)
+        ));
+        assert!(matches!(
+            MrAction::for_status_type(T::OnHold, S::Resolved),
+            Some(MrAction::Acknowledge)
+        ));
+        assert!(matches!(
+            MrAction::for_status_type(T::Resolved, S::Acknowledged),
+            Some(MrAction::Resolve)
+        ));
+        assert!(matches!(
+            MrAction::for_status_type(T::Closed, S::Open),
+            Some(MrAction::Close)
+        ));
+        assert!(matches!(
+            MrAction::for_status_type(T::Open, S::Cancelled),
+            Some(MrAction::Reopen)
+        ));
+    }
+
+    #[test]
+    fn for_status_type_moves_to_a_status_of_the_type() {
+        for status_type in TYPES {
+            for state in STATES {
+                if let Some(action) = MrAction::for_status_type(status_type, state) {
+                    assert!(action.status_types().contains(&status_type));
+                }
+            }
+        }
     }
 }
@@ -0,0 +1,220 @@
+use axum::debug_handler;
+use axum::extract::Path;
+use axum::Json;
+use serde::{Deserialize, Serialize};
+
+use crate::db;
+use crate::errors::{AppError, AppResult, CommonResponse};
+use crate::repo::{check_references, Tenant};
+use crate::utils::JwtClaims;
+use crate::DB;
+
+db::mr_status_transition::select! {
+    transition_out {
+        id
+        from_status: select {
+            id
+            status_code
+            status_name
+            status_type
+        }
+        to_status: select {
+            id
+            status_code
+            status_name
+            status_type
+        }
+        roles: select {
+            id
+            role_name
+        }
+    }
+}
+
+db::mr_status_transition::select! { transition_roles {
+    roles: select {
+        id
+    }
+}}
+
+/// Fails unless the workflow of the company lets the role move an mr from
+/// one status to the other, a company without any transitions has no
+/// workflow and every change is allowed.
+pub async fn check_transition(
+    tenant: Tenant<'_>,
+    role_id: i32,
+    from: i32,
+    to: i32,
+) -> AppResult<()> {
+    if from == to {
+        return Ok(());
+    }
+    let transitions = tenant.mr_status_transition();
+    if transitions.count(vec![]).exec().await? == 0 {
+        return Ok(());
+    }
+    let transition = transitions
+        .find_first(vec![
+            db::mr_status_transition::from_status_id::equals(from),
+            db::mr_status_transition::to_status_id::equals(to),
+        ])
+        .select(transition_roles::select())
+        .exec()
+        .await?;
+    match transition {
+        None => Err(AppError::Custom {
+            status_code: 409,
+            error: format!(
+                "the workflow does not allow moving an mr from status {} to {}",
+                from, to
+            ),
+        }),
+        Some(t) if !t.roles.is_empty() && !t.roles.iter().any(|r| r.id == role_id) => {
+            Err(AppError::Custom {
+                status_code: 403,
+                error: format!(
+                    "your role is not allowed to move an mr from status {} to {}",
+                    from, to
+                ),
+            })
+        }
+        Some(_) => Ok(()),
+    }
+}
+
+/// Every id has to be a role of the company.
+async fn check_roles(tenant: Tenant<'_>, role_ids: &[i32]) -> AppResult<()> {
+    let count = tenant
+        .role()
+        .count(vec![db::role::id::in_vec(role_ids.to_vec())])
+        .exec()
+        .await?;
+    let mut unique = role_ids.to_vec();
+    unique.sort_unstable();
+    unique.dedup();
+    check_references(vec![("role_ids", count == unique.len() as i64)])
+}
+
+fn role_links(role_ids: Vec<i32>) -> Vec<db::role::UniqueWhereParam> {
+    role_ids.into_iter().map(db::role::id::equals).collect()
+}
+
+#[debug_handler]
+pub async fn list_mr_transitions(
+    c: JwtClaims,
+) -> AppResult<Json<CommonResponse<Vec<transition_out::Data>>>> {
+    c.check_module_privilige(db::Module::MaintainanceRequest, db::PrivilegeType::View)
+        .await?;
+    let client = DB.get().unwrap();
+    CommonResponse::json_data(
+        c.tenant(client)
+            .mr_status_transition()
+            .find_many(vec![])
+            .select(transition_out::select())
+            .exec()
+            .await?,
+    )
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateTransitionInfo {
+    pub from_status_id: i32,
+    pub to_status_id: i32,
+    /// the roles that may take the transition, every role when empty
+    #[serde(default)]
+    pub role_ids: Vec<i32>,
+}
+
+#[debug_handler]
+pub async fn create_mr_transition(
+    c: JwtClaims,
+    Json(payload): Json<CreateTransitionInfo>,
+) -> AppResult<Json<CommonResponse<transition_out::Data>>> {
+    c.check_module_privilige(db::Module::Admin, db::PrivilegeType::Edit)
+        .await?;
+    let client = DB.get().unwrap();
+    let tenant = c.tenant(client);
+    check_references(vec![
+        (
+            "from_status_id",
+            tenant.mr_status().owns(payload.from_status_id).await?,
+        ),
+        (
+            "to_status_id",
+            tenant.mr_status().owns(payload.to_status_id).await?,
+        ),
+    ])?;
+    if payload.from_status_id == payload.to_status_id {
+        return Err(AppError::InvalidFields(vec!["to_status_id".to_string()]));
+    }
+    check_roles(tenant, &payload.role_ids).await?;
+    CommonResponse::json_data(
+        tenant
+            .mr_status_transition()
+            .create(|transitions, company| {
+                transitions.create(
+                    company,
+                    db::mr_status::id::equals(payload.from_status_id),
+                    db::mr_status::id::equals(payload.to_status_id),
+                    vec![db::mr_status_transition::roles::connect(role_links(
+                        payload.role_ids,
+                    ))],
+                )
+            })
+            .select(transition_out::select())
+            .exec()
+            .await?,
+    )
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UpdateTransitionInfo {
+    /// replaces the roles that may take the transition, every role when empty
+    pub role_ids: Vec<i32>,
+}
+
+#[debug_handler]
+pub async fn update_mr_transition(
+    Path(id): Path<i32>,
+    c: JwtClaims,
+    Json(payload): Json<UpdateTransitionInfo>,
+) -> AppResult<Json<CommonResponse<transition_out::Data>>> {
+    c.check_module_privilige(db::Module::Admin, db::PrivilegeType::Edit)
+        .await?;
+    let client = DB.get().unwrap();
+    let tenant = c.tenant(client);
+    check_roles(tenant, &payload.role_ids).await?;
+    CommonResponse::json_data(
+        tenant
+            .mr_status_transition()
+            .update(
+                id,
+                vec![db::mr_status_transition::roles::set(role_links(
+                    payload.role_ids,
+                ))],
+            )
+            .await?
+            .select(transition_out::select())
+            .exec()
+            .await?,
+    )
+}
+
+#[debug_handler]
+pub async fn delete_mr_transition(
+    Path(id): Path<i32>,
+    c: JwtClaims,
+) -> AppResult<Json<CommonResponse<transition_out::Data>>> {
+    c.check_module_privilige(db::Module::Admin, db::PrivilegeType::Edit)
+        .await?;
+    let client = DB.get().unwrap();
+    CommonResponse::json_data(
+        c.tenant(client)
+            .mr_status_transition()
+            .delete(id)
+            .await?
+            .select(transition_out::select())
+            .exec()
+            .await?,
+    )
+}
@@ -0,0 +1,82 @@
+use crate::db;
+use crate::errors::{AppError, AppResult, CommonResponse};
+use crate::utils::PlatformAdmin;
+use crate::DB;
+
+use axum::debug_handler;
+use axum::extract::Path;
+use axum::Json;
+use chrono::{DateTime, FixedOffset, Months, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SubscriptionInfo {
+    /// new expiration date, takes precedence over `extend_months`
+    pub expiration_date: Option<DateTime<FixedOffset>>,
+    /// extend from the current expiration date, or from now if already expired
+    pub extend_months: Option<u32>,
+    pub max_users: Option<i32>,
+}
+
+db::company::select! {
+    subscription_out {
+        id
+        company_code
+        company_name
+        expiration_date
+        max_users
+    }
+}
+
+#[debug_handler]
+pub async fn update_subscription(
+    Path(id): Path<i32>,
+    _p: PlatformAdmin,
+    Json(payload): Json<SubscriptionInfo>,
+) -> AppResult<Json<CommonResponse<subscription_out::Data>>> {
+    let client = DB.get().unwrap();
+    let company = client
+        .company()
+        .find_unique(db::company::id::equals(id))
+        .exec()
+        .await?
+        .ok_or(AppError::Custom {
+            status_code: 404,
+            error: "company not found".to_string(),
+        })?;
+    let mut params = vec![];
+    if let Some(date) = payload.expiration_date {
+        params.push(db::company::expiration_date::set(date));
+    } else if let Some(months) = payload.extend_months {
+        let now: DateTime<FixedOffset> = Utc::now().into();
+        let from = company.expiration_date.max(now);
+        let until = from
+            .checked_add_months(Months::new(months))
+            .ok_or(AppError::Custom {
+                status_code: 400,
+                error: "extend_months is out of range".to_string(),
+            })?;
+        params.push(db::company::expiration_date::set(until));
+    }
+    if let Some(max_users) = payload.max_users {
+        if max_users < 1 {
+            return Err(AppError::Custom {
+                status_code: 400,
+                error: "max_users must be at least 1".to_string(),
+            });
+        }
+        params.push(db::company::max_users::set(max_users));
+    }
+    let c = client
+        .company()
+        .update(db::company::id::equals(id), params)
+        .select(subscription_out::select())
+        .exec()
+        .await?;
+    info!(
+        "subscription of company {} now expires at {} with {} users",
+        c.id, c.expiration_date, c.max_users
+    );
+    CommonResponse::json_data(c)
+}
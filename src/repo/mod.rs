@@ -0,0 +1,338 @@
+use chrono::{DateTime, FixedOffset, Utc};
+use prisma_client_rust::Direction;
+use serde::{Deserialize, Serialize};
+
+use crate::db::{self, PrismaClient};
+use crate::errors::{AppError, AppResult};
+use crate::utils::JwtClaims;
+
+const DEFAULT_PAGE_SIZE: i64 = 20;
+const MAX_PAGE_SIZE: i64 = 100;
+
+/// Data access scoped to a single company. Every query on a company owned
+/// model goes through here so that it is always filtered by `company_id`.
+#[derive(Clone, Copy)]
+pub struct Tenant<'a> {
+    client: &'a PrismaClient,
+    company_id: i32,
+}
+
+impl<'a> Tenant<'a> {
+    pub fn new(client: &'a PrismaClient, company_id: i32) -> Self {
+        Self { client, company_id }
+    }
+
+    pub fn company(&self) -> CompanyRepo<'a> {
+        CompanyRepo {
+            client: self.client,
+            company_id: self.company_id,
+        }
+    }
+}
+
+impl JwtClaims {
+    pub fn tenant<'a>(&self, client: &'a PrismaClient) -> Tenant<'a> {
+        Tenant::new(client, self.company_id)
+    }
+}
+
+/// Fails with a 422 naming every field whose referenced id is not owned by
+/// the company, takes `(field, owned)` pairs built with `owns`/`owns_opt`.
+pub fn check_references(fields: Vec<(&'static str, bool)>) -> AppResult<()> {
+    let invalid: Vec<String> = fields
+        .into_iter()
+        .filter(|(_, owned)| !owned)
+        .map(|(f, _)| f.to_string())
+        .collect();
+    if invalid.is_empty() {
+        Ok(())
+    } else {
+        Err(AppError::InvalidReferences(invalid))
+    }
+}
+
+/// Fails with a 409 when live records still reference the one to delete,
+/// takes `(records, count)` pairs such as `("assets", 3)`.
+pub fn check_unused(label: &str, used_by: Vec<(&'static str, i64)>) -> AppResult<()> {
+    let used: Vec<String> = used_by
+        .into_iter()
+        .filter(|(_, count)| *count > 0)
+        .map(|(records, count)| format!("{} {}", count, records))
+        .collect();
+    if used.is_empty() {
+        Ok(())
+    } else {
+        Err(AppError::Custom {
+            status_code: 409,
+            error: format!("{} is still used by {}", label, used.join(", ")),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+impl From<SortOrder> for Direction {
+    fn from(o: SortOrder) -> Self {
+        match o {
+            SortOrder::Asc => Direction::Asc,
+            SortOrder::Desc => Direction::Desc,
+        }
+    }
+}
+
+/// The number of rows to return for a requested `limit`.
+pub fn page_size(limit: Option<i64>) -> i64 {
+    limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE)
+}
+
+/// One page of a cursor paginated listing, `next_cursor` is sent back as
+/// `cursor` to fetch the following page.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub total: i64,
+    pub next_cursor: Option<i32>,
+}
+
+impl<T> Page<T> {
+    /// `items` are fetched with `take(size + 1)`, the extra row only tells
+    /// whether there is a following page.
+    pub fn new(mut items: Vec<T>, total: i64, size: i64, id: impl Fn(&T) -> i32) -> Self {
+        let next_cursor = if items.len() as i64 > size {
+            items.truncate(size as usize);
+            items.last().map(id)
+        } else {
+            None
+        };
+        Self {
+            items,
+            total,
+            next_cursor,
+        }
+    }
+}
+
+fn not_found(label: &str) -> AppError {
+    AppError::Custom {
+        status_code: 404,
+        error: format!("{} not found", label),
+    }
+}
+
+/// The company of the tenant itself.
+pub struct CompanyRepo<'a> {
+    client: &'a PrismaClient,
+    company_id: i32,
+}
+
+impl<'a> CompanyRepo<'a> {
+    /// Only the caller's own company can be updated.
+    pub fn update(
+        &self,
+        id: i32,
+        params: Vec<db::company::SetParam>,
+    ) -> AppResult<db::company::Update<'a>> {
+        if id != self.company_id {
+            return Err(not_found("company"));
+        }
+        Ok(self
+            .client
+            .company()
+            .update(db::company::id::equals(id), params))
+    }
+}
+
+macro_rules! tenant_repo {
+    (@soft_delete $filters:ident, $model:ident) => {
+        $filters.push(db::$model::deleted_at::equals(None));
+    };
+    (@soft_delete $model:ident, $repo:ident, $label:literal) => {
+        // not every model needs every accessor
+        #[allow(dead_code)]
+        impl<'a> $repo<'a> {
+            fn trash_scope(&self, mut filters: Vec<db::$model::WhereParam>) -> Vec<db::$model::WhereParam> {
+                filters.push(db::$model::company_id::equals(self.company_id));
+                filters.push(db::$model::deleted_at::not(None));
+                filters
+            }
+
+            /// Mark the record as deleted, which hides it from every other
+            /// query of the repository.
+            pub async fn soft_delete(&self, id: i32) -> AppResult<db::$model::Update<'a>> {
+                self.update(id, vec![db::$model::deleted_at::set(Some(Utc::now().into()))])
+                    .await
+            }
+
+            /// The deleted records of the company.
+            pub fn find_deleted(&self, filters: Vec<db::$model::WhereParam>) -> db::$model::FindMany<'a> {
+                self.client.$model().find_many(self.trash_scope(filters))
+            }
+
+            /// Fails with a 404 unless the record is a deleted one of the company.
+            pub async fn restore(&self, id: i32) -> AppResult<db::$model::Update<'a>> {
+                let count = self
+                    .client
+                    .$model()
+                    .count(self.trash_scope(vec![db::$model::id::equals(id)]))
+                    .exec()
+                    .await?;
+                if count == 0 {
+                    return Err(not_found($label));
+                }
+                Ok(self
+                    .client
+                    .$model()
+                    .update(db::$model::id::equals(id), vec![db::$model::deleted_at::set(None)]))
+            }
+
+            /// Remove the records deleted before `before` for good.
+            pub fn purge(
+                &self,
+                before: DateTime<FixedOffset>,
+                mut filters: Vec<db::$model::WhereParam>,
+            ) -> db::$model::DeleteMany<'a> {
+                filters.push(db::$model::deleted_at::lt(before));
+                self.client.$model().delete_many(self.trash_scope(filters))
+            }
+        }
+    };
+    ($($(#[$soft:ident])? $model:ident: $repo:ident => $label:literal),* $(,)?) => {
+        impl<'a> Tenant<'a> {
+            $(
+                pub fn $model(&self) -> $repo<'a> {
+                    $repo {
+                        client: self.client,
+                        company_id: self.company_id,
+                    }
+                }
+            )*
+        }
+
+        $(
+            pub struct $repo<'a> {
+                client: &'a PrismaClient,
+                company_id: i32,
+            }
+
+            // not every model needs every accessor
+            #[allow(dead_code)]
+            impl<'a> $repo<'a> {
+                fn scope(&self, mut filters: Vec<db::$model::WhereParam>) -> Vec<db::$model::WhereParam> {
+                    filters.push(db::$model::company_id::equals(self.company_id));
+                    $(tenant_repo!(@$soft filters, $model);)?
+                    filters
+                }
+
+                /// Create a record of the company, `create` gets the model's
+                /// actions along with the company to connect it to.
+                pub fn create(
+                    &self,
+                    create: impl FnOnce(db::$model::Actions<'a>, db::company::UniqueWhereParam) -> db::$model::Create<'a>,
+                ) -> db::$model::Create<'a> {
+                    create(self.client.$model(), db::company::id::equals(self.company_id))
+                }
+
+                pub fn find_many(&self, filters: Vec<db::$model::WhereParam>) -> db::$model::FindMany<'a> {
+                    self.client.$model().find_many(self.scope(filters))
+                }
+
+                pub fn find_first(&self, filters: Vec<db::$model::WhereParam>) -> db::$model::FindFirst<'a> {
+                    self.client.$model().find_first(self.scope(filters))
+                }
+
+                pub fn find_by_id(&self, id: i32) -> db::$model::FindFirst<'a> {
+                    self.find_first(vec![db::$model::id::equals(id)])
+                }
+
+                pub fn count(&self, filters: Vec<db::$model::WhereParam>) -> db::$model::Count<'a> {
+                    self.client.$model().count(self.scope(filters))
+                }
+
+                pub fn update_many(
+                    &self,
+                    filters: Vec<db::$model::WhereParam>,
+                    params: Vec<db::$model::SetParam>,
+                ) -> db::$model::UpdateMany<'a> {
+                    self.client.$model().update_many(self.scope(filters), params)
+                }
+
+                /// Fails with a 404 unless the record belongs to the company.
+                pub async fn ensure(&self, id: i32) -> AppResult<()> {
+                    if self.owns(id).await? {
+                        Ok(())
+                    } else {
+                        Err(not_found($label))
+                    }
+                }
+
+                /// Whether the id references a record of the company.
+                pub async fn owns(&self, id: i32) -> AppResult<bool> {
+                    let count = self
+                        .count(vec![db::$model::id::equals(id)])
+                        .exec()
+                        .await?;
+                    Ok(count > 0)
+                }
+
+                /// Like `owns`, an absent reference is always valid.
+                pub async fn owns_opt(&self, id: Option<i32>) -> AppResult<bool> {
+                    match id {
+                        Some(id) => self.owns(id).await,
+                        None => Ok(true),
+                    }
+                }
+
+                /// Fetch a record of the company or fail with a 404.
+                pub async fn get(&self, id: i32) -> AppResult<db::$model::Data> {
+                    self.find_by_id(id)
+                        .exec()
+                        .await?
+                        .ok_or_else(|| not_found($label))
+                }
+
+                pub async fn update(
+                    &self,
+                    id: i32,
+                    params: Vec<db::$model::SetParam>,
+                ) -> AppResult<db::$model::Update<'a>> {
+                    self.ensure(id).await?;
+                    Ok(self.client.$model().update(db::$model::id::equals(id), params))
+                }
+
+                pub async fn delete(&self, id: i32) -> AppResult<db::$model::Delete<'a>> {
+                    self.ensure(id).await?;
+                    Ok(self.client.$model().delete(db::$model::id::equals(id)))
+                }
+            }
+
+            $(tenant_repo!(@$soft $model, $repo, $label);)?
+        )*
+    };
+}
+
+// `#[soft_delete]` models are deleted by setting `deleted_at`, their deleted
+// records are left out of every query unless asked for explicitly.
+tenant_repo! {
+    #[soft_delete] user: UserRepo => "user",
+    #[soft_delete] role: RoleRepo => "role",
+    invitation: InvitationRepo => "invitation",
+    #[soft_delete] asset: AssetRepo => "asset",
+    #[soft_delete] asset_location: AssetLocationRepo => "location",
+    #[soft_delete] asset_status: AssetStatusRepo => "status",
+    #[soft_delete] maintainance_request: MaintainanceRequestRepo => "mr",
+    #[soft_delete] mr_status: MrStatusRepo => "mr status",
+    #[soft_delete] mr_category: MrCategoryRepo => "mr category",
+    #[soft_delete] mr_priority: MrPriorityRepo => "mr priority",
+    #[soft_delete] mr_failure_impact: MrFailureImpactRepo => "mr failure impact",
+    #[soft_delete] mr_failure_mode: MrFailureModeRepo => "mr failure mode",
+    #[soft_delete] work_order: WorkOrderRepo => "work order",
+    #[soft_delete] custom_field_definition: CustomFieldDefinitionRepo => "custom field",
+    asset_event: AssetEventRepo => "asset event",
+    mr_status_transition: MrStatusTransitionRepo => "mr status transition",
+}
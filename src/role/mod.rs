@@ -0,0 +1,423 @@
+use crate::db::{self, PrismaClient};
+use crate::errors::{AppError, AppResult, CommonResponse};
+use crate::repo::Tenant;
+use crate::user::revoke_user_sessions;
+use crate::utils::{invalidate_role_privileges, JwtClaims};
+use crate::DB;
+
+use axum::debug_handler;
+use axum::extract::Path;
+use axum::Json;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct PrivilegeInfo {
+    pub module: db::Module,
+    pub privilege_type: db::PrivilegeType,
+}
+
+db::role::select! {
+    role_out {
+        id
+        company_id
+        role_name
+        is_default
+        role_privileges: select {
+            module
+            privilege_type
+        }
+    }
+}
+
+/// Every module a privilege can be granted on.
+pub const ALL_MODULES: [db::Module; 6] = [
+    db::Module::Dashboard,
+    db::Module::Asset,
+    db::Module::Location,
+    db::Module::Admin,
+    db::Module::MaintainanceRequest,
+    db::Module::WorkOrder,
+];
+
+pub const ADMINISTRATOR_ROLE: &str = "Administrator";
+
+/// Roles every new company starts with, `privilege` maps each module to the
+/// privilege the role gets on it.
+struct RoleTemplate {
+    name: &'static str,
+    is_default: bool,
+    privilege: fn(db::Module) -> db::PrivilegeType,
+}
+
+const ROLE_TEMPLATES: [RoleTemplate; 3] = [
+    RoleTemplate {
+        name: ADMINISTRATOR_ROLE,
+        is_default: false,
+        privilege: |_| db::PrivilegeType::Edit,
+    },
+    RoleTemplate {
+        name: "Technician",
+        is_default: false,
+        privilege: |m| match m {
+            db::Module::Asset | db::Module::MaintainanceRequest | db::Module::WorkOrder => {
+                db::PrivilegeType::Edit
+            }
+            db::Module::Admin => db::PrivilegeType::None,
+            _ => db::PrivilegeType::View,
+        },
+    },
+    RoleTemplate {
+        name: "Viewer",
+        is_default: true,
+        privilege: |m| match m {
+            db::Module::Admin => db::PrivilegeType::None,
+            _ => db::PrivilegeType::View,
+        },
+    },
+];
+
+/// Create the template roles with a full privilege matrix for a new company,
+/// returns the created roles in template order.
+pub async fn create_default_roles(
+    client: &PrismaClient,
+    company_id: i32,
+) -> AppResult<Vec<db::role::Data>> {
+    let tenant = Tenant::new(client, company_id);
+    let mut roles = vec![];
+    for t in ROLE_TEMPLATES.iter() {
+        let r = tenant
+            .role()
+            .create(|records, company| {
+                records.create(
+                    t.name.to_string(),
+                    company,
+                    vec![db::role::is_default::set(t.is_default)],
+                )
+            })
+            .exec()
+            .await?;
+        let privileges: Vec<_> = ALL_MODULES
+            .iter()
+            .map(|m| PrivilegeInfo {
+                module: *m,
+                privilege_type: (t.privilege)(*m),
+            })
+            .collect();
+        set_privileges(client, r.id, &privileges).await?;
+        roles.push(r);
+    }
+    Ok(roles)
+}
+
+fn is_admin_capable(privileges: &[PrivilegeInfo]) -> bool {
+    privileges
+        .iter()
+        .any(|p| p.module == db::Module::Admin && p.privilege_type == db::PrivilegeType::Edit)
+}
+
+fn check_privilege_matrix(privileges: &[PrivilegeInfo]) -> AppResult<()> {
+    for (i, p) in privileges.iter().enumerate() {
+        if privileges[..i].iter().any(|o| o.module == p.module) {
+            return Err(AppError::Custom {
+                status_code: 400,
+                error: format!("duplicate privilege for module {}", p.module.to_string()),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// A company must always keep at least one role able to administrate it,
+/// otherwise nobody could ever fix its roles again.
+async fn ensure_other_admin_role(tenant: Tenant<'_>, role_id: i32) -> AppResult<()> {
+    let others = tenant
+        .role()
+        .count(vec![
+            db::role::id::not(role_id),
+            db::role::role_privileges::some(admin_privilege()),
+        ])
+        .exec()
+        .await?;
+    if others == 0 {
+        Err(AppError::Custom {
+            status_code: 400,
+            error: "can not remove the last admin role of the company".to_string(),
+        })
+    } else {
+        Ok(())
+    }
+}
+
+fn admin_privilege() -> Vec<db::privilege::WhereParam> {
+    vec![
+        db::privilege::module::equals(db::Module::Admin),
+        db::privilege::privilege_type::equals(db::PrivilegeType::Edit),
+    ]
+}
+
+/// Whether the role of the company can administrate it.
+pub async fn role_grants_admin(tenant: Tenant<'_>, role_id: i32) -> AppResult<bool> {
+    let count = tenant
+        .role()
+        .count(vec![
+            db::role::id::equals(role_id),
+            db::role::role_privileges::some(admin_privilege()),
+        ])
+        .exec()
+        .await?;
+    Ok(count > 0)
+}
+
+/// An admin role nobody holds does not help either, some active user has to
+/// keep one. `others` selects the users left untouched by the change.
+pub async fn ensure_other_admin_user(
+    tenant: Tenant<'_>,
+    others: Vec<db::user::WhereParam>,
+) -> AppResult<()> {
+    let mut filters = vec![
+        db::user::is_active::equals(db::IsActive::Yes),
+        db::user::role::is(vec![db::role::role_privileges::some(admin_privilege())]),
+    ];
+    filters.extend(others);
+    if tenant.user().count(filters).exec().await? == 0 {
+        Err(AppError::Custom {
+            status_code: 400,
+            error: "can not remove the last administrator of the company".to_string(),
+        })
+    } else {
+        Ok(())
+    }
+}
+
+async fn find_role(tenant: Tenant<'_>, id: i32) -> AppResult<role_out::Data> {
+    tenant
+        .role()
+        .find_by_id(id)
+        .select(role_out::select())
+        .exec()
+        .await?
+        .ok_or(AppError::Custom {
+            status_code: 404,
+            error: "role not found".to_string(),
+        })
+}
+
+async fn set_privileges(
+    client: &PrismaClient,
+    role_id: i32,
+    privileges: &[PrivilegeInfo],
+) -> AppResult<()> {
+    client
+        .privilege()
+        .delete_many(vec![db::privilege::role_id::equals(role_id)])
+        .exec()
+        .await?;
+    for p in privileges {
+        client
+            .privilege()
+            .create(
+                p.privilege_type,
+                db::role::id::equals(role_id),
+                p.module,
+                vec![],
+            )
+            .exec()
+            .await?;
+    }
+    Ok(())
+}
+
+#[debug_handler]
+pub async fn list_roles(c: JwtClaims) -> AppResult<Json<CommonResponse<Vec<role_out::Data>>>> {
+    c.check_module_privilige(db::Module::Admin, db::PrivilegeType::Edit)
+        .await?;
+    let client = DB.get().unwrap();
+    CommonResponse::json_data(
+        c.tenant(client)
+            .role()
+            .find_many(vec![])
+            .select(role_out::select())
+            .exec()
+            .await?,
+    )
+}
+
+#[debug_handler]
+pub async fn get_role(
+    Path(id): Path<i32>,
+    c: JwtClaims,
+) -> AppResult<Json<CommonResponse<role_out::Data>>> {
+    c.check_module_privilige(db::Module::Admin, db::PrivilegeType::Edit)
+        .await?;
+    let client = DB.get().unwrap();
+    CommonResponse::json_data(find_role(c.tenant(client), id).await?)
+}
+
+#[debug_handler]
+pub async fn get_role_by_name(
+    Path(name): Path<String>,
+    c: JwtClaims,
+) -> AppResult<Json<CommonResponse<role_out::Data>>> {
+    c.check_module_privilige(db::Module::Admin, db::PrivilegeType::Edit)
+        .await?;
+    let client = DB.get().unwrap();
+    let r = c
+        .tenant(client)
+        .role()
+        .find_first(vec![db::role::role_name::equals(name)])
+        .select(role_out::select())
+        .exec()
+        .await?;
+    match r {
+        Some(role) => CommonResponse::json_data(role),
+        _ => Err(AppError::Custom {
+            status_code: 404,
+            error: "role not found".to_string(),
+        }),
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateRoleInfo {
+    pub role_name: String,
+    pub privileges: Vec<PrivilegeInfo>,
+}
+
+#[debug_handler]
+pub async fn create_role(
+    c: JwtClaims,
+    Json(payload): Json<CreateRoleInfo>,
+) -> AppResult<Json<CommonResponse<role_out::Data>>> {
+    c.check_module_privilige(db::Module::Admin, db::PrivilegeType::Edit)
+        .await?;
+    check_privilege_matrix(&payload.privileges)?;
+    let client = DB.get().unwrap();
+    let company_id = c.company_id;
+    let r = client
+        ._transaction()
+        .run(|client| async move {
+            let tenant = Tenant::new(&client, company_id);
+            let r = tenant
+                .role()
+                .create(|roles, company| roles.create(payload.role_name, company, vec![]))
+                .exec()
+                .await?;
+            set_privileges(&client, r.id, &payload.privileges).await?;
+            find_role(tenant, r.id).await
+        })
+        .await?;
+    CommonResponse::json_data(r)
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UpdateRoleInfo {
+    pub role_name: Option<String>,
+    /// replaces the whole privilege matrix of the role when present
+    pub privileges: Option<Vec<PrivilegeInfo>>,
+}
+
+#[debug_handler]
+pub async fn update_role(
+    Path(id): Path<i32>,
+    c: JwtClaims,
+    Json(payload): Json<UpdateRoleInfo>,
+) -> AppResult<Json<CommonResponse<role_out::Data>>> {
+    c.check_module_privilige(db::Module::Admin, db::PrivilegeType::Edit)
+        .await?;
+    let client = DB.get().unwrap();
+    let company_id = c.company_id;
+    let r = client
+        ._transaction()
+        .run(|client| async move {
+            let tenant = Tenant::new(&client, company_id);
+            let role = find_role(tenant, id).await?;
+            if let Some(privileges) = &payload.privileges {
+                check_privilege_matrix(privileges)?;
+                let was_admin = role.role_privileges.iter().any(|p| {
+                    p.module == db::Module::Admin && p.privilege_type == db::PrivilegeType::Edit
+                });
+                if was_admin && !is_admin_capable(privileges) {
+                    ensure_other_admin_role(tenant, id).await?;
+                    ensure_other_admin_user(tenant, vec![db::user::role_id::not(id)]).await?;
+                }
+                set_privileges(&client, id, privileges).await?;
+            }
+            if let Some(role_name) = payload.role_name {
+                client
+                    .role()
+                    .update(
+                        db::role::id::equals(id),
+                        vec![db::role::role_name::set(role_name)],
+                    )
+                    .exec()
+                    .await?;
+            }
+            find_role(tenant, id).await
+        })
+        .await?;
+    invalidate_role_privileges(id);
+    CommonResponse::json_data(r)
+}
+
+#[debug_handler]
+pub async fn delete_role(
+    Path(id): Path<i32>,
+    c: JwtClaims,
+) -> AppResult<Json<CommonResponse<role_out::Data>>> {
+    c.check_module_privilige(db::Module::Admin, db::PrivilegeType::Edit)
+        .await?;
+    let client = DB.get().unwrap();
+    let company_id = c.company_id;
+    let r = client
+        ._transaction()
+        .run(|client| async move {
+            let tenant = Tenant::new(&client, company_id);
+            let role = find_role(tenant, id).await?;
+            let users = tenant
+                .user()
+                .count(vec![db::user::role_id::equals(id)])
+                .exec()
+                .await?;
+            if users > 0 {
+                return Err(AppError::Custom {
+                    status_code: 400,
+                    error: format!("role is still assigned to {} users", users),
+                });
+            }
+            ensure_other_admin_role(tenant, id).await?;
+            // the privileges stay so that a restored role grants them again
+            tenant.role().soft_delete(id).await?.exec().await?;
+            Ok(role)
+        })
+        .await?;
+    invalidate_role_privileges(id);
+    CommonResponse::json_data(r)
+}
+
+#[debug_handler]
+pub async fn assign_role(Path((id, user_id)): Path<(i32, i32)>, c: JwtClaims) -> AppResult<()> {
+    c.check_module_privilige(db::Module::Admin, db::PrivilegeType::Edit)
+        .await?;
+    let client = DB.get().unwrap();
+    let tenant = c.tenant(client);
+    tenant.role().ensure(id).await?;
+    if !role_grants_admin(tenant, id).await? {
+        ensure_other_admin_user(tenant, vec![db::user::id::not(user_id)]).await?;
+    }
+    let updated = tenant
+        .user()
+        .update_many(
+            vec![db::user::id::equals(user_id)],
+            vec![db::user::role_id::set(id)],
+        )
+        .exec()
+        .await?;
+    if updated == 0 {
+        return Err(AppError::Custom {
+            status_code: 404,
+            error: "user not found".to_string(),
+        });
+    }
+    revoke_user_sessions(client, user_id).await?;
+    Ok(())
+}
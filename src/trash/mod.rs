@@ -0,0 +1,479 @@
+use axum::debug_handler;
+use axum::extract::{Path, Query};
+use axum::Json;
+use chrono::{DateTime, Duration, FixedOffset, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::errors::{AppResult, CommonResponse};
+use crate::repo::{check_references, Tenant};
+use crate::utils::{invalidate_role_privileges, JwtClaims};
+use crate::{db, DB};
+
+/// Deleted records are kept this many days before they can be purged,
+/// unless `TRASH_RETENTION_DAYS` says otherwise.
+const DEFAULT_RETENTION_DAYS: i64 = 30;
+
+fn retention_days() -> i64 {
+    std::env::var("TRASH_RETENTION_DAYS")
+        .ok()
+        .and_then(|d| d.parse().ok())
+        .unwrap_or(DEFAULT_RETENTION_DAYS)
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TrashKind {
+    Asset,
+    Location,
+    Status,
+    Mr,
+    MrStatus,
+    MrCategory,
+    MrPriority,
+    MrFailureImpact,
+    MrFailureMode,
+    WorkOrder,
+    Role,
+    CustomField,
+}
+
+impl TrashKind {
+    fn module(self) -> db::Module {
+        match self {
+            TrashKind::Asset | TrashKind::Status => db::Module::Asset,
+            TrashKind::Location => db::Module::Location,
+            TrashKind::WorkOrder => db::Module::WorkOrder,
+            TrashKind::Role | TrashKind::CustomField => db::Module::Admin,
+            _ => db::Module::MaintainanceRequest,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TrashQuery {
+    pub kind: TrashKind,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TrashItem {
+    pub id: i32,
+    /// absent for maintenance requests, work orders and roles, which have no
+    /// code
+    pub code: Option<String>,
+    pub name: String,
+    pub deleted_at: Option<DateTime<FixedOffset>>,
+}
+
+macro_rules! trash_items {
+    ($tenant:expr, $model:ident, $code:ident, $name:ident) => {
+        $tenant
+            .$model()
+            .find_deleted(vec![])
+            .exec()
+            .await?
+            .into_iter()
+            .map(|r| TrashItem {
+                id: r.id,
+                code: Some(r.$code),
+                name: r.$name,
+                deleted_at: r.deleted_at,
+            })
+            .collect()
+    };
+}
+
+#[debug_handler]
+pub async fn list_trash(
+    c: JwtClaims,
+    Query(q): Query<TrashQuery>,
+) -> AppResult<Json<CommonResponse<Vec<TrashItem>>>> {
+    c.check_module_privilige(q.kind.module(), db::PrivilegeType::View)
+        .await?;
+    let client = DB.get().unwrap();
+    let tenant = c.tenant(client);
+    let mut items: Vec<TrashItem> = match q.kind {
+        TrashKind::Asset => trash_items!(tenant, asset, asset_code, asset_name),
+        TrashKind::Location => trash_items!(tenant, asset_location, location_code, location_name),
+        TrashKind::Status => trash_items!(tenant, asset_status, status_code, status_name),
+        TrashKind::Mr => tenant
+            .maintainance_request()
+            .find_deleted(vec![])
+            .exec()
+            .await?
+            .into_iter()
+            .map(|r| TrashItem {
+                id: r.id,
+                code: None,
+                name: r.mr_name,
+                deleted_at: r.deleted_at,
+            })
+            .collect(),
+        TrashKind::MrStatus => trash_items!(tenant, mr_status, status_code, status_name),
+        TrashKind::MrCategory => trash_items!(tenant, mr_category, category_code, category_name),
+        TrashKind::MrPriority => trash_items!(tenant, mr_priority, priority_code, priority_name),
+        TrashKind::MrFailureImpact => trash_items!(
+            tenant,
+            mr_failure_impact,
+            failure_impact_code,
+            failure_impact_name
+        ),
+        TrashKind::MrFailureMode => {
+            trash_items!(
+                tenant,
+                mr_failure_mode,
+                failure_mode_code,
+                failure_mode_name
+            )
+        }
+        TrashKind::WorkOrder => tenant
+            .work_order()
+            .find_deleted(vec![])
+            .exec()
+            .await?
+            .into_iter()
+            .map(|w| TrashItem {
+                id: w.id,
+                code: None,
+                name: w.wo_name,
+                deleted_at: w.deleted_at,
+            })
+            .collect(),
+        TrashKind::Role => tenant
+            .role()
+            .find_deleted(vec![])
+            .exec()
+            .await?
+            .into_iter()
+            .map(|r| TrashItem {
+                id: r.id,
+                code: None,
+                name: r.role_name,
+                deleted_at: r.deleted_at,
+            })
+            .collect(),
+        TrashKind::CustomField => {
+            trash_items!(tenant, custom_field_definition, field_key, label)
+        }
+    };
+    items.sort_by(|a, b| b.deleted_at.cmp(&a.deleted_at));
+    CommonResponse::json_data(items)
+}
+
+/// The records a deleted one references have to be restored before it.
+async fn check_restorable(tenant: Tenant<'_>, kind: TrashKind, id: i32) -> AppResult<()> {
+    match kind {
+        TrashKind::Asset => {
+            if let Some(a) = tenant
+                .asset()
+                .find_deleted(vec![db::asset::id::equals(id)])
+                .exec()
+                .await?
+                .pop()
+            {
+                check_references(vec![
+                    (
+                        "asset_location_id",
+                        tenant.asset_location().owns(a.asset_location_id).await?,
+                    ),
+                    (
+                        "asset_status_id",
+                        tenant.asset_status().owns(a.asset_status_id).await?,
+                    ),
+                    ("asset_id", tenant.asset().owns_opt(a.asset_id).await?),
+                ])?;
+            }
+        }
+        TrashKind::Location => {
+            if let Some(l) = tenant
+                .asset_location()
+                .find_deleted(vec![db::asset_location::id::equals(id)])
+                .exec()
+                .await?
+                .pop()
+            {
+                check_references(vec![(
+                    "parent_id",
+                    tenant.asset_location().owns_opt(l.parent_id).await?,
+                )])?;
+            }
+        }
+        TrashKind::Mr => {
+            if let Some(m) = tenant
+                .maintainance_request()
+                .find_deleted(vec![db::maintainance_request::id::equals(id)])
+                .exec()
+                .await?
+                .pop()
+            {
+                check_references(vec![
+                    ("asset_id", tenant.asset().owns(m.asset_id).await?),
+                    (
+                        "mr_status_id",
+                        tenant.mr_status().owns(m.mr_status_id).await?,
+                    ),
+                    (
+                        "mr_category_id",
+                        tenant.mr_category().owns(m.mr_category_id).await?,
+                    ),
+                    (
+                        "mr_priority_id",
+                        tenant.mr_priority().owns(m.mr_priority_id).await?,
+                    ),
+                    (
+                        "mr_failure_impact_id",
+                        tenant
+                            .mr_failure_impact()
+                            .owns(m.mr_failure_impact_id)
+                            .await?,
+                    ),
+                    (
+                        "mr_failure_mode_id",
+                        tenant.mr_failure_mode().owns(m.mr_failure_mode_id).await?,
+                    ),
+                ])?;
+            }
+        }
+        TrashKind::WorkOrder => {
+            if let Some(w) = tenant
+                .work_order()
+                .find_deleted(vec![db::work_order::id::equals(id)])
+                .exec()
+                .await?
+                .pop()
+            {
+                check_references(vec![
+                    ("asset_id", tenant.asset().owns(w.asset_id).await?),
+                    (
+                        "mr_id",
+                        tenant.maintainance_request().owns_opt(w.mr_id).await?,
+                    ),
+                ])?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+#[debug_handler]
+pub async fn restore_trash(
+    Path((kind, id)): Path<(TrashKind, i32)>,
+    c: JwtClaims,
+) -> AppResult<()> {
+    c.check_module_privilige(kind.module(), db::PrivilegeType::Edit)
+        .await?;
+    let client = DB.get().unwrap();
+    let tenant = c.tenant(client);
+    check_restorable(tenant, kind, id).await?;
+    match kind {
+        TrashKind::Asset => {
+            tenant.asset().restore(id).await?.exec().await?;
+        }
+        TrashKind::Location => {
+            tenant.asset_location().restore(id).await?.exec().await?;
+        }
+        TrashKind::Status => {
+            tenant.asset_status().restore(id).await?.exec().await?;
+        }
+        TrashKind::Mr => {
+            tenant
+                .maintainance_request()
+                .restore(id)
+                .await?
+                .exec()
+                .await?;
+        }
+        TrashKind::MrStatus => {
+            tenant.mr_status().restore(id).await?.exec().await?;
+        }
+        TrashKind::MrCategory => {
+            tenant.mr_category().restore(id).await?.exec().await?;
+        }
+        TrashKind::MrPriority => {
+            tenant.mr_priority().restore(id).await?.exec().await?;
+        }
+        TrashKind::MrFailureImpact => {
+            tenant.mr_failure_impact().restore(id).await?.exec().await?;
+        }
+        TrashKind::MrFailureMode => {
+            tenant.mr_failure_mode().restore(id).await?.exec().await?;
+        }
+        TrashKind::WorkOrder => {
+            tenant.work_order().restore(id).await?.exec().await?;
+        }
+        TrashKind::Role => {
+            tenant.role().restore(id).await?.exec().await?;
+            invalidate_role_privileges(id);
+        }
+        TrashKind::CustomField => {
+            tenant
+                .custom_field_definition()
+                .restore(id)
+                .await?
+                .exec()
+                .await?;
+        }
+    }
+    info!("restored {:?} {} of company {}", kind, id, c.company_id);
+    Ok(())
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct PurgeReport {
+    /// records deleted before this time were purged
+    pub before: DateTime<FixedOffset>,
+    pub work_orders: i64,
+    pub mrs: i64,
+    pub mr_lookups: i64,
+    pub assets: i64,
+    pub statuses: i64,
+    pub locations: i64,
+    pub roles: i64,
+    pub custom_fields: i64,
+}
+
+/// Remove the records deleted longer than the retention period ago. Records
+/// still referenced by other deleted records are kept until those are
+/// purged too.
+#[debug_handler]
+pub async fn purge_trash(c: JwtClaims) -> AppResult<Json<CommonResponse<PurgeReport>>> {
+    c.check_module_privilige(db::Module::Admin, db::PrivilegeType::Edit)
+        .await?;
+    let client = DB.get().unwrap();
+    let tenant = c.tenant(client);
+    let before: DateTime<FixedOffset> = (Utc::now() - Duration::days(retention_days())).into();
+    let mut report = PurgeReport {
+        before,
+        ..Default::default()
+    };
+    report.work_orders = tenant.work_order().purge(before, vec![]).exec().await?;
+    report.mrs = tenant
+        .maintainance_request()
+        .purge(
+            before,
+            vec![db::maintainance_request::work_orders::none(vec![])],
+        )
+        .exec()
+        .await?;
+    report.mr_lookups = tenant
+        .mr_status()
+        .purge(
+            before,
+            vec![db::mr_status::maintainance_request::none(vec![])],
+        )
+        .exec()
+        .await?
+        + tenant
+            .mr_category()
+            .purge(
+                before,
+                vec![db::mr_category::maintainance_request::none(vec![])],
+            )
+            .exec()
+            .await?
+        + tenant
+            .mr_priority()
+            .purge(
+                before,
+                vec![db::mr_priority::maintainance_request::none(vec![])],
+            )
+            .exec()
+            .await?
+        + tenant
+            .mr_failure_impact()
+            .purge(
+                before,
+                vec![db::mr_failure_impact::maintainance_request::none(vec![])],
+            )
+            .exec()
+            .await?
+        + tenant
+            .mr_failure_mode()
+            .purge(
+                before,
+                vec![db::mr_failure_mode::maintainance_request::none(vec![])],
+            )
+            .exec()
+            .await?;
+    // components go before the assets they were built into
+    loop {
+        let purged = tenant
+            .asset()
+            .purge(
+                before,
+                vec![
+                    db::asset::children_asset::none(vec![]),
+                    db::asset::maintainance_request::none(vec![]),
+                    db::asset::work_orders::none(vec![]),
+                ],
+            )
+            .exec()
+            .await?;
+        if purged == 0 {
+            break;
+        }
+        report.assets += purged;
+    }
+    report.statuses = tenant
+        .asset_status()
+        .purge(before, vec![db::asset_status::asset::none(vec![])])
+        .exec()
+        .await?;
+    loop {
+        let purged = tenant
+            .asset_location()
+            .purge(
+                before,
+                vec![
+                    db::asset_location::children_location::none(vec![]),
+                    db::asset_location::asset::none(vec![]),
+                ],
+            )
+            .exec()
+            .await?;
+        if purged == 0 {
+            break;
+        }
+        report.locations += purged;
+    }
+    // roles users or invitations still point at are kept, the privileges of
+    // the others go with them
+    let roles: Vec<i32> = tenant
+        .role()
+        .find_deleted(vec![
+            db::role::deleted_at::lt(before),
+            db::role::users::none(vec![]),
+            db::role::invitations::none(vec![]),
+        ])
+        .exec()
+        .await?
+        .into_iter()
+        .map(|r| r.id)
+        .collect();
+    if !roles.is_empty() {
+        let company_id = c.company_id;
+        report.roles = client
+            ._transaction()
+            .run(|client| async move {
+                client
+                    .privilege()
+                    .delete_many(vec![db::privilege::role_id::in_vec(roles.clone())])
+                    .exec()
+                    .await?;
+                Tenant::new(&client, company_id)
+                    .role()
+                    .purge(before, vec![db::role::id::in_vec(roles)])
+                    .exec()
+                    .await
+            })
+            .await?;
+    }
+    report.custom_fields = tenant
+        .custom_field_definition()
+        .purge(before, vec![])
+        .exec()
+        .await?;
+    info!("purged trash of company {}: {:?}", c.company_id, report);
+    CommonResponse::json_data(report)
+}
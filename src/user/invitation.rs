@@ -0,0 +1,177 @@
+use axum::{debug_handler, extract::Path, Json};
+use chrono::{DateTime, Duration, FixedOffset, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::session::{hash_token, new_token, now};
+use crate::{
+    db::{self, PrismaClient},
+    errors::{AppError, AppResult, CommonResponse},
+    repo::{check_references, Tenant},
+    utils::JwtClaims,
+    DB,
+};
+
+const DEFAULT_INVITATION_DAYS: i64 = 7;
+const MAX_INVITATION_DAYS: i64 = 90;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateInvitationInfo {
+    pub email: String,
+    pub role_id: i32,
+    pub expires_in_days: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InvitationOutInfo {
+    pub id: i32,
+    /// only returned once, the invitee needs it to register
+    pub token: String,
+    pub expires_at: DateTime<FixedOffset>,
+}
+
+db::invitation::select! {
+    invitation_out {
+        id
+        email
+        role: select {
+            id
+            role_name
+        }
+        user_id
+        expires_at
+        consumed_at
+    }
+}
+
+#[debug_handler]
+pub async fn create_invitation(
+    c: JwtClaims,
+    Json(payload): Json<CreateInvitationInfo>,
+) -> AppResult<Json<CommonResponse<InvitationOutInfo>>> {
+    c.check_module_privilige(db::Module::Admin, db::PrivilegeType::Edit)
+        .await?;
+    let client = DB.get().unwrap();
+    check_references(vec![(
+        "role_id",
+        c.tenant(client).role().owns(payload.role_id).await?,
+    )])?;
+    let token = new_token();
+    let days = payload
+        .expires_in_days
+        .unwrap_or(DEFAULT_INVITATION_DAYS)
+        .clamp(1, MAX_INVITATION_DAYS);
+    let i = c
+        .tenant(client)
+        .invitation()
+        .create(|invitations, company| {
+            invitations.create(
+                company,
+                payload.email,
+                db::role::id::equals(payload.role_id),
+                db::user::id::equals(c.user_id),
+                hash_token(&token),
+                (Utc::now() + Duration::days(days)).into(),
+                vec![],
+            )
+        })
+        .exec()
+        .await?;
+    CommonResponse::json_data(InvitationOutInfo {
+        id: i.id,
+        token,
+        expires_at: i.expires_at,
+    })
+}
+
+#[debug_handler]
+pub async fn list_invitations(
+    c: JwtClaims,
+) -> AppResult<Json<CommonResponse<Vec<invitation_out::Data>>>> {
+    c.check_module_privilige(db::Module::Admin, db::PrivilegeType::Edit)
+        .await?;
+    let client = DB.get().unwrap();
+    CommonResponse::json_data(
+        c.tenant(client)
+            .invitation()
+            .find_many(vec![
+                db::invitation::consumed_at::equals(None),
+                db::invitation::expires_at::gt(now()),
+            ])
+            .select(invitation_out::select())
+            .exec()
+            .await?,
+    )
+}
+
+/// Revoked invitations are deleted for good rather than moved to the trash,
+/// restoring one would make a token the invitee was told is dead valid again.
+#[debug_handler]
+pub async fn revoke_invitation(Path(id): Path<i32>, c: JwtClaims) -> AppResult<()> {
+    c.check_module_privilige(db::Module::Admin, db::PrivilegeType::Edit)
+        .await?;
+    let client = DB.get().unwrap();
+    let tenant = c.tenant(client);
+    if tenant.invitation().get(id).await?.consumed_at.is_some() {
+        return Err(AppError::Custom {
+            status_code: 400,
+            error: "invitation has already been used".to_string(),
+        });
+    }
+    tenant.invitation().delete(id).await?.exec().await?;
+    Ok(())
+}
+
+/// Look up a pending invitation of the company issued to `email`.
+pub async fn find_invitation(
+    client: &PrismaClient,
+    company_id: i32,
+    email: &str,
+    token: &str,
+) -> AppResult<db::invitation::Data> {
+    let invalid = || AppError::Custom {
+        status_code: 403,
+        error: "invalid invitation".to_string(),
+    };
+    let i = client
+        .invitation()
+        .find_unique(db::invitation::token_hash::equals(hash_token(token)))
+        .exec()
+        .await?
+        .ok_or_else(invalid)?;
+    if i.company_id != company_id
+        || i.consumed_at.is_some()
+        || i.expires_at < now()
+        || !i.email.eq_ignore_ascii_case(email)
+    {
+        return Err(invalid());
+    }
+    // the role to join with may have been deleted since
+    let tenant = Tenant::new(client, company_id);
+    if !tenant.role().owns(i.role_id).await? {
+        return Err(invalid());
+    }
+    Ok(i)
+}
+
+/// Mark the invitation as used, fails if another registration consumed it
+/// first.
+pub async fn consume_invitation(client: &PrismaClient, id: i32) -> AppResult<()> {
+    let updated = client
+        .invitation()
+        .update_many(
+            vec![
+                db::invitation::id::equals(id),
+                db::invitation::consumed_at::equals(None),
+            ],
+            vec![db::invitation::consumed_at::set(Some(now()))],
+        )
+        .exec()
+        .await?;
+    if updated == 0 {
+        return Err(AppError::Custom {
+            status_code: 409,
+            error: "invitation has already been used".to_string(),
+        });
+    }
+    Ok(())
+}
@@ -1,18 +1,32 @@
 use anyhow::Result;
 use axum::{debug_handler, extract::Path, Json};
-use chrono::{Days, FixedOffset, Local, Months};
-use jsonwebtoken;
+use chrono::{Local, Months};
+use prisma_client_rust::{raw, PrismaValue};
 use serde::{Deserialize, Serialize};
-use sha2::{Digest, Sha256};
 use tracing::{debug, info};
 
 use crate::{
-    db::{self, company},
+    custom_field::validate_custom_fields,
+    db::{self, company, PrismaClient},
     errors::{AppError, AppResult, CommonResponse},
-    utils::{JwtClaims, KEYS},
+    field_policy,
+    repo::{check_references, Tenant},
+    role::{create_default_roles, ensure_other_admin_user, role_grants_admin, ADMINISTRATOR_ROLE},
+    utils::{subscription_expired, JwtClaims},
     DB,
 };
 
+mod password;
+use password::{hash_password, verify_password, PasswordCheck};
+mod invitation;
+use invitation::{consume_invitation, find_invitation};
+pub use invitation::{create_invitation, list_invitations, revoke_invitation};
+mod password_reset;
+pub use password_reset::{change_password, forgot_password, reset_password};
+mod session;
+pub use session::revoke_user_sessions;
+use session::{create_session, revoke_session, rotate_session, TokenPair};
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct UserRegisterInfo {
     pub company_code: String,
@@ -22,16 +36,56 @@ pub struct UserRegisterInfo {
     pub telephone: String,
     pub address: Option<String>,
     pub email: String,
-    pub customize_fileds_1: Option<String>,
-    pub customize_fileds_2: Option<String>,
-    pub customize_fileds_3: Option<String>,
-    pub customize_fileds_4: Option<String>,
-    pub customize_fileds_5: Option<String>,
+    pub custom_fields: Option<serde_json::Value>,
+    /// required unless the company allows open signup
+    pub invitation_token: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CompanyLimit {
+    max_users: i32,
+}
+
+/// Companies can not have more users than their subscription allows. Meant to
+/// run in the transaction creating the user, the company row stays locked
+/// until it ends so concurrent registrations are counted one after the other.
+async fn ensure_user_capacity(client: &PrismaClient, company_id: i32) -> AppResult<()> {
+    let limit: Vec<CompanyLimit> = client
+        ._query_raw(raw!(
+            "SELECT max_users FROM \"Company\" WHERE id = {} FOR UPDATE",
+            PrismaValue::Int(company_id as i64)
+        ))
+        .exec()
+        .await?;
+    let max_users = match limit.first() {
+        Some(l) => l.max_users,
+        None => {
+            return Err(AppError::Custom {
+                status_code: 404,
+                error: "company not found".to_string(),
+            })
+        }
+    };
+    let users = client
+        .user()
+        .count(vec![
+            db::user::company_id::equals(company_id),
+            db::user::deleted_at::equals(None),
+        ])
+        .exec()
+        .await?;
+    if users >= max_users as i64 {
+        return Err(AppError::Custom {
+            status_code: 403,
+            error: format!("company has reached its limit of {} users", max_users),
+        });
+    }
+    Ok(())
 }
 
 #[debug_handler]
-pub async fn user_register(Json(ur): Json<UserRegisterInfo>) -> AppResult<()> {
-    debug!("user register info: {:?}", ur);
+pub async fn user_register(Json(mut ur): Json<UserRegisterInfo>) -> AppResult<()> {
+    debug!("user register info: {:?}", ur.username);
     let client = DB.get().unwrap();
     let company = client
         .company()
@@ -40,41 +94,78 @@ pub async fn user_register(Json(ur): Json<UserRegisterInfo>) -> AppResult<()> {
         .await?;
     match company {
         Some(c) => {
-            let admin_role = client
-                .role()
-                .find_first(vec![db::role::company_id::equals(c.id)])
-                .exec()
-                .await?;
-            let role = match admin_role {
-                Some(r) => r,
-                _ => {
+            if subscription_expired(&c) {
+                return Err(AppError::Custom {
+                    status_code: 402,
+                    error: "company subscription has expired".to_string(),
+                });
+            }
+            let invitation = match &ur.invitation_token {
+                Some(token) => Some(find_invitation(client, c.id, &ur.email, token).await?),
+                None if c.open_signup => None,
+                None => {
+                    return Err(AppError::Custom {
+                        status_code: 403,
+                        error: "an invitation is required to join this company".to_string(),
+                    })
+                }
+            };
+            let role_id = match &invitation {
+                Some(i) => i.role_id,
+                None => {
                     client
                         .role()
-                        .create("admin".into(), company::id::equals(c.id), vec![])
+                        .find_first(vec![
+                            db::role::company_id::equals(c.id),
+                            db::role::is_default::equals(true),
+                            db::role::deleted_at::equals(None),
+                        ])
                         .exec()
                         .await?
+                        .ok_or(AppError::Custom {
+                            status_code: 400,
+                            error: "company has no default role".to_string(),
+                        })?
+                        .id
                 }
             };
+            let custom_fields = validate_custom_fields(
+                Tenant::new(client, c.id),
+                db::CustomFieldEntity::User,
+                ur.custom_fields.take(),
+                None,
+            )
+            .await?;
+            let password = hash_password(&ur.password)?;
             let u = client
-                .user()
-                .create(
-                    company::company_code::equals(ur.company_code),
-                    crate::db::IsActive::Yes,
-                    ur.username,
-                    format!("{:X}", Sha256::digest(ur.password)),
-                    ur.nickname,
-                    ur.telephone,
-                    ur.email,
-                    db::role::id::equals(role.id),
-                    vec![
-                        db::user::customize_fileds_1::set(ur.customize_fileds_1),
-                        db::user::customize_fileds_2::set(ur.customize_fileds_2),
-                        db::user::customize_fileds_3::set(ur.customize_fileds_3),
-                        db::user::customize_fileds_4::set(ur.customize_fileds_4),
-                        db::user::customize_fileds_5::set(ur.customize_fileds_5),
-                    ],
-                )
-                .exec()
+                ._transaction()
+                .run(|client| async move {
+                    ensure_user_capacity(&client, c.id).await?;
+                    if let Some(i) = invitation {
+                        consume_invitation(&client, i.id).await?;
+                    }
+                    let u = Tenant::new(&client, c.id)
+                        .user()
+                        .create(|users, company| {
+                            users.create(
+                                company,
+                                crate::db::IsActive::Yes,
+                                ur.username,
+                                password,
+                                ur.nickname,
+                                ur.telephone,
+                                ur.email,
+                                db::role::id::equals(role_id),
+                                vec![
+                                    db::user::address::set(ur.address),
+                                    db::user::custom_fields::set(custom_fields),
+                                ],
+                            )
+                        })
+                        .exec()
+                        .await?;
+                    Ok::<_, AppError>(u)
+                })
                 .await?;
             info!("successfully create user, id: {}", u.id);
             Ok(())
@@ -96,59 +187,101 @@ pub struct UserLoginInfo {
 pub struct LoginOutInfo {
     id: i32,
     token: String,
+    refresh_token: String,
 }
 
 #[debug_handler]
 pub async fn user_login(
     Json(ul): Json<UserLoginInfo>,
 ) -> AppResult<Json<CommonResponse<LoginOutInfo>>> {
-    debug!("user login info: {:?}", ul);
-    let encryped_password = format!("{:X}", Sha256::digest(ul.password));
+    debug!("user login info: {:?}", ul.username);
     let client = DB.get().unwrap();
     let u = client
         .user()
-        .find_first(vec![db::user::username::equals(ul.username)])
+        .find_first(vec![
+            db::user::username::equals(ul.username),
+            db::user::deleted_at::equals(None),
+        ])
+        .with(db::user::company::fetch())
         .exec()
         .await?;
-    match u {
-        Some(user) => match user.is_active {
-            db::IsActive::Yes => {
-                debug!("find active user: {:?}", user);
-                if encryped_password != user.password {
-                    Err(AppError::Custom {
-                        status_code: 403,
-                        error: "wrong password".to_string(),
-                    })
-                } else {
-                    let exp = Local::now() + Days::new(14);
-                    let exp = exp.with_timezone(&FixedOffset::east_opt(8 * 3600).unwrap());
-                    let claim = JwtClaims {
-                        user_id: user.id,
-                        company_id: user.company_id,
-                        role_id: user.role_id,
-                        exp: exp.timestamp(),
-                    };
-                    let token = jsonwebtoken::encode(
-                        &jsonwebtoken::Header::default(),
-                        &claim,
-                        &KEYS.encoding,
-                    )?;
-                    Ok(Json(CommonResponse {
-                        data: Some(LoginOutInfo { token, id: user.id }),
-                        error: None,
-                    }))
-                }
-            }
-            _ => Err(AppError::Custom {
-                status_code: 403,
-                error: "user is not active".to_string(),
-            }),
-        },
-        _ => Err(AppError::Custom {
-            status_code: 400,
-            error: "user does not exist".to_string(),
-        }),
+    // the password is checked first, the state of the account and of its
+    // company is only revealed to whoever knows it
+    let user = match u {
+        Some(user) => user,
+        _ => {
+            return Err(AppError::Custom {
+                status_code: 400,
+                error: "user does not exist".to_string(),
+            })
+        }
+    };
+    let check = verify_password(&ul.password, &user.password);
+    if check == PasswordCheck::Invalid {
+        return Err(AppError::Custom {
+            status_code: 403,
+            error: "wrong password".to_string(),
+        });
     }
+    if user.is_active != db::IsActive::Yes {
+        return Err(AppError::Custom {
+            status_code: 403,
+            error: "user is not active".to_string(),
+        });
+    }
+    debug!("find active user: {:?}", user.id);
+    if user
+        .company()
+        .map(|c| subscription_expired(c))
+        .unwrap_or(true)
+    {
+        return Err(AppError::Custom {
+            status_code: 402,
+            error: "company subscription has expired".to_string(),
+        });
+    }
+    if check == PasswordCheck::ValidLegacy {
+        info!("migrating legacy password hash of user {}", user.id);
+        client
+            .user()
+            .update(
+                db::user::id::equals(user.id),
+                vec![db::user::password::set(hash_password(&ul.password)?)],
+            )
+            .exec()
+            .await?;
+    }
+    let tokens = create_session(client, &user).await?;
+    Ok(Json(CommonResponse {
+        data: Some(LoginOutInfo {
+            id: user.id,
+            token: tokens.token,
+            refresh_token: tokens.refresh_token,
+        }),
+        error: None,
+        fields: None,
+    }))
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RefreshInfo {
+    refresh_token: String,
+}
+
+#[debug_handler]
+pub async fn user_refresh(
+    Json(r): Json<RefreshInfo>,
+) -> AppResult<Json<CommonResponse<TokenPair>>> {
+    let client = DB.get().unwrap();
+    CommonResponse::json_data(rotate_session(client, &r.refresh_token).await?)
+}
+
+#[debug_handler]
+pub async fn user_logout(c: JwtClaims) -> AppResult<()> {
+    let client = DB.get().unwrap();
+    revoke_session(client, c.session_id).await?;
+    info!("user {} logged out of session {}", c.user_id, c.session_id);
+    Ok(())
 }
 
 db::user::select! { user_out {
@@ -158,11 +291,7 @@ db::user::select! { user_out {
         company_name
         company_code
         expiration_date
-        customize_fileds_1
-        customize_fileds_2
-        customize_fileds_3
-        customize_fileds_4
-        customize_fileds_5
+        custom_fields
     }
     username
     email
@@ -172,11 +301,7 @@ db::user::select! { user_out {
         id
         role_name
     }
-    customize_fileds_1
-    customize_fileds_2
-    customize_fileds_3
-    customize_fileds_4
-    customize_fileds_5
+    custom_fields
 }}
 
 pub async fn user_details(
@@ -188,12 +313,10 @@ pub async fn user_details(
             .await?;
     }
     let client = DB.get().unwrap();
-    let u = client
+    let u = c
+        .tenant(client)
         .user()
-        .find_first(vec![
-            db::user::id::equals(id),
-            db::user::company_id::equals(c.company_id),
-        ])
+        .find_by_id(id)
         .select(user_out::select())
         .exec()
         .await?;
@@ -210,45 +333,79 @@ db::user::partial!(
     UpdateUserInfo {
         nickname
         address
-        password
         telephone
-        customize_fileds_1
-        customize_fileds_2
-        customize_fileds_3
-        customize_fileds_4
-        customize_fileds_5
+        custom_fields
         role_id
+        is_active
     }
 );
 
+field_policy!(UpdateUserInfo {
+    role_id => (Admin, Edit),
+    is_active => (Admin, Edit),
+});
+
 #[debug_handler]
 pub async fn update_user(
     Path(id): Path<i32>,
     c: JwtClaims,
-    Json(payload): Json<UpdateUserInfo>,
+    Json(mut payload): Json<UpdateUserInfo>,
 ) -> AppResult<Json<CommonResponse<user_out::Data>>> {
     let client = DB.get().unwrap();
-    if id == c.user_id {
-        CommonResponse::json_data(
-            client
-                .user()
-                .update(db::user::id::equals(id), payload.to_params())
-                .select(user_out::select())
-                .exec()
-                .await?,
-        )
-    } else {
+    if id != c.user_id {
         c.check_module_privilige(db::Module::Admin, db::PrivilegeType::Edit)
             .await?;
-        CommonResponse::json_data(
-            client
-                .user()
-                .update(db::user::id::equals(id), payload.to_params())
-                .select(user_out::select())
-                .exec()
-                .await?,
-        )
     }
+    c.check_field_privileges(&payload).await?;
+    let tenant = c.tenant(client);
+    check_references(vec![(
+        "role_id",
+        tenant.role().owns_opt(payload.role_id).await?,
+    )])?;
+    let deactivated = payload.is_active.map_or(false, |a| a != db::IsActive::Yes);
+    let demoted = match payload.role_id {
+        Some(role_id) => !role_grants_admin(tenant, role_id).await?,
+        None => false,
+    };
+    if deactivated || demoted {
+        ensure_other_admin_user(tenant, vec![db::user::id::not(id)]).await?;
+    }
+    let revoke_sessions = payload.role_id.is_some() || payload.is_active.is_some();
+    let mut params = vec![];
+    if let Some(values) = payload.custom_fields.take() {
+        let user = tenant.user().get(id).await?;
+        params.push(db::user::custom_fields::set(
+            validate_custom_fields(
+                tenant,
+                db::CustomFieldEntity::User,
+                Some(values),
+                Some(&user.custom_fields),
+            )
+            .await?,
+        ));
+    }
+    params.extend(payload.to_params());
+    let u = tenant
+        .user()
+        .update(id, params)
+        .await?
+        .select(user_out::select())
+        .exec()
+        .await?;
+    if revoke_sessions {
+        revoke_user_sessions(client, id).await?;
+    }
+    CommonResponse::json_data(u)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AdminRegisterInfo {
+    pub username: String,
+    pub password: String,
+    pub nickname: String,
+    pub telephone: String,
+    pub address: Option<String>,
+    pub email: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -259,15 +416,12 @@ pub struct CompanyRegisterInfo {
     pub email: String,
     pub telephone: String,
     pub address: Option<String>,
-    pub customize_fileds_1: Option<String>,
-    pub customize_fileds_2: Option<String>,
-    pub customize_fileds_3: Option<String>,
-    pub customize_fileds_4: Option<String>,
-    pub customize_fileds_5: Option<String>,
+    /// the first user of the company, it gets the administrator role
+    pub admin: AdminRegisterInfo,
 }
 
 pub async fn company_register(Json(cr): Json<CompanyRegisterInfo>) -> AppResult<()> {
-    debug!("{:?}", cr);
+    debug!("company register info: {:?}", cr.company_code);
     let client = DB.get().unwrap();
     let find = client
         .company()
@@ -282,19 +436,52 @@ pub async fn company_register(Json(cr): Json<CompanyRegisterInfo>) -> AppResult<
     } else {
         let expire_date = Local::now() + Months::new(12);
         debug!("{:?}", expire_date);
-        client
-            .company()
-            .create(
-                cr.company_code,
-                cr.company_name,
-                cr.email,
-                cr.telephone,
-                cr.company_bid,
-                expire_date.into(),
-                vec![],
-            )
-            .exec()
+        let password = hash_password(&cr.admin.password)?;
+        let (company, user) = client
+            ._transaction()
+            .run(|client| async move {
+                let company = client
+                    .company()
+                    .create(
+                        cr.company_code,
+                        cr.company_name,
+                        cr.email,
+                        cr.telephone,
+                        cr.company_bid,
+                        expire_date.into(),
+                        vec![db::company::address::set(cr.address)],
+                    )
+                    .exec()
+                    .await?;
+                let roles = create_default_roles(&client, company.id).await?;
+                let admin_role = roles
+                    .iter()
+                    .find(|r| r.role_name == ADMINISTRATOR_ROLE)
+                    .unwrap();
+                let user = Tenant::new(&client, company.id)
+                    .user()
+                    .create(|users, company| {
+                        users.create(
+                            company,
+                            db::IsActive::Yes,
+                            cr.admin.username,
+                            password,
+                            cr.admin.nickname,
+                            cr.admin.telephone,
+                            cr.admin.email,
+                            db::role::id::equals(admin_role.id),
+                            vec![db::user::address::set(cr.admin.address)],
+                        )
+                    })
+                    .exec()
+                    .await?;
+                Ok::<_, AppError>((company, user))
+            })
             .await?;
+        info!(
+            "successfully create company {} with administrator {}",
+            company.id, user.id
+        );
         Ok(())
     }
 }
@@ -305,21 +492,46 @@ db::company::partial!(
         email
         telephone
         address
+        open_signup
+        custom_fields
+});
+
+field_policy!(UpdateCompanyInfo {
+    company_name => (Admin, Edit),
+    email => (Admin, Edit),
+    telephone => (Admin, Edit),
+    address => (Admin, Edit),
+    open_signup => (Admin, Edit),
+    custom_fields => (Admin, Edit),
 });
 
 pub async fn update_company(
     Path(id): Path<i32>,
     c: JwtClaims,
-    Json(payload): Json<UpdateCompanyInfo>,
+    Json(mut payload): Json<UpdateCompanyInfo>,
 ) -> AppResult<Json<CommonResponse<db::company::Data>>> {
     c.check_module_privilige(db::Module::Admin, db::PrivilegeType::Edit)
         .await?;
+    c.check_field_privileges(&payload).await?;
     let client = DB.get().unwrap();
-    CommonResponse::json_data(
-        client
+    let tenant = c.tenant(client);
+    let mut params = vec![];
+    if let Some(values) = payload.custom_fields.take() {
+        let company = client
             .company()
-            .update(db::company::id::equals(id), payload.to_params())
+            .find_unique(db::company::id::equals(c.company_id))
             .exec()
+            .await?;
+        params.push(db::company::custom_fields::set(
+            validate_custom_fields(
+                tenant,
+                db::CustomFieldEntity::Company,
+                Some(values),
+                company.as_ref().map(|c| &c.custom_fields),
+            )
             .await?,
-    )
+        ));
+    }
+    params.extend(payload.to_params());
+    CommonResponse::json_data(tenant.company().update(id, params)?.exec().await?)
 }
@@ -0,0 +1,90 @@
+use anyhow::{anyhow, Result};
+use argon2::{
+    password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Argon2,
+};
+use sha2::{Digest, Sha256};
+
+/// Outcome of checking a plain text password against a stored hash.
+#[derive(Debug, PartialEq, Eq)]
+pub enum PasswordCheck {
+    /// the password matches an up-to-date argon2id hash
+    Valid,
+    /// the password matches a legacy unsalted sha256 hash and must be rehashed
+    ValidLegacy,
+    Invalid,
+}
+
+/// Hash a password into a PHC string (`$argon2id$v=19$...`), the prefix
+/// carries the algorithm and version so the format can evolve later.
+pub fn hash_password(password: &str) -> Result<String> {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|h| h.to_string())
+        .map_err(|e| anyhow!("failed to hash password: {}", e))
+}
+
+pub fn verify_password(password: &str, stored: &str) -> PasswordCheck {
+    if stored.starts_with('$') {
+        match PasswordHash::new(stored) {
+            Ok(parsed) => {
+                if Argon2::default()
+                    .verify_password(password.as_bytes(), &parsed)
+                    .is_ok()
+                {
+                    PasswordCheck::Valid
+                } else {
+                    PasswordCheck::Invalid
+                }
+            }
+            Err(_) => PasswordCheck::Invalid,
+        }
+    } else if format!("{:X}", Sha256::digest(password)) == stored {
+        PasswordCheck::ValidLegacy
+    } else {
+        PasswordCheck::Invalid
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// sha256 of `secret` as the legacy hashes were stored
+    const LEGACY_SECRET: &str = "2BB80D537B1DA3E38BD30361AA855686BDE0EACD7162FEF6A25FE97BF527A25B";
+
+    #[test]
+    fn argon2_hashes_verify() {
+        let stored = hash_password("secret").unwrap();
+        assert!(stored.starts_with("$argon2id$"));
+        assert_eq!(verify_password("secret", &stored), PasswordCheck::Valid);
+        assert_eq!(verify_password("Secret", &stored), PasswordCheck::Invalid);
+    }
+
+    #[test]
+    fn legacy_hashes_ask_for_a_rehash() {
+        assert_eq!(
+            verify_password("secret", LEGACY_SECRET),
+            PasswordCheck::ValidLegacy
+        );
+        assert_eq!(
+            verify_password("Secret", LEGACY_SECRET),
+            PasswordCheck::Invalid
+        );
+        assert_eq!(
+            verify_password("secret", &LEGACY_SECRET.to_lowercase()),
+            PasswordCheck::Invalid
+        );
+    }
+
+    #[test]
+    fn invalid_hashes_never_verify() {
+        assert_eq!(
+            verify_password("secret", "$argon2id$broken"),
+            PasswordCheck::Invalid
+        );
+        assert_eq!(verify_password("secret", "$"), PasswordCheck::Invalid);
+        assert_eq!(verify_password("", ""), PasswordCheck::Invalid);
+    }
+}
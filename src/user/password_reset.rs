@@ -0,0 +1,191 @@
+use axum::{debug_handler, Json};
+use chrono::{Duration, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::{error, info};
+
+use super::password::{hash_password, verify_password, PasswordCheck};
+use super::session::{hash_token, new_token, now, revoke_user_sessions};
+use crate::{
+    db,
+    errors::{AppError, AppResult},
+    mailer::Mail,
+    utils::JwtClaims,
+    DB, MAILER,
+};
+
+const RESET_TOKEN_MINUTES: i64 = 60;
+const MIN_PASSWORD_LENGTH: usize = 8;
+
+fn check_password_policy(password: &str) -> AppResult<()> {
+    if password.chars().count() < MIN_PASSWORD_LENGTH {
+        return Err(AppError::Custom {
+            status_code: 400,
+            error: format!(
+                "password must be at least {} characters long",
+                MIN_PASSWORD_LENGTH
+            ),
+        });
+    }
+    Ok(())
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ChangePasswordInfo {
+    current_password: String,
+    new_password: String,
+}
+
+/// Change the password of the current user, every other session of the user
+/// is revoked.
+#[debug_handler]
+pub async fn change_password(
+    c: JwtClaims,
+    Json(payload): Json<ChangePasswordInfo>,
+) -> AppResult<()> {
+    check_password_policy(&payload.new_password)?;
+    let client = DB.get().unwrap();
+    let user = client
+        .user()
+        .find_unique(db::user::id::equals(c.user_id))
+        .exec()
+        .await?
+        .ok_or(AppError::Custom {
+            status_code: 404,
+            error: "user not found".to_string(),
+        })?;
+    if verify_password(&payload.current_password, &user.password) == PasswordCheck::Invalid {
+        return Err(AppError::Custom {
+            status_code: 403,
+            error: "wrong password".to_string(),
+        });
+    }
+    client
+        .user()
+        .update(
+            db::user::id::equals(user.id),
+            vec![db::user::password::set(hash_password(
+                &payload.new_password,
+            )?)],
+        )
+        .exec()
+        .await?;
+    client
+        .session()
+        .update_many(
+            vec![
+                db::session::user_id::equals(user.id),
+                db::session::id::not(c.session_id),
+                db::session::revoked_at::equals(None),
+            ],
+            vec![db::session::revoked_at::set(Some(now()))],
+        )
+        .exec()
+        .await?;
+    info!("user {} changed their password", user.id);
+    Ok(())
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ForgotPasswordInfo {
+    email: String,
+}
+
+/// Always succeeds so that the endpoint can not be used to probe which emails
+/// are registered.
+#[debug_handler]
+pub async fn forgot_password(Json(payload): Json<ForgotPasswordInfo>) -> AppResult<()> {
+    let client = DB.get().unwrap();
+    let user = client
+        .user()
+        .find_unique(db::user::email::equals(payload.email))
+        .exec()
+        .await?;
+    let user = match user {
+        Some(u) if u.is_active == db::IsActive::Yes => u,
+        _ => return Ok(()),
+    };
+    let token = new_token();
+    client
+        .password_reset()
+        .create(
+            db::user::id::equals(user.id),
+            hash_token(&token),
+            (Utc::now() + Duration::minutes(RESET_TOKEN_MINUTES)).into(),
+            vec![],
+        )
+        .exec()
+        .await?;
+    let mail = Mail {
+        to: user.email,
+        subject: "Reset your password".to_string(),
+        body: format!(
+            "Use the following token to reset your password, it expires in {} minutes:\n\n{}",
+            RESET_TOKEN_MINUTES, token
+        ),
+    };
+    if let Err(e) = MAILER.get().unwrap().send(&mail).await {
+        error!(
+            "failed to send password reset mail to user {}: {}",
+            user.id, e
+        );
+    }
+    Ok(())
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ResetPasswordInfo {
+    token: String,
+    new_password: String,
+}
+
+#[debug_handler]
+pub async fn reset_password(Json(payload): Json<ResetPasswordInfo>) -> AppResult<()> {
+    check_password_policy(&payload.new_password)?;
+    let client = DB.get().unwrap();
+    let invalid = || AppError::Custom {
+        status_code: 400,
+        error: "invalid or expired reset token".to_string(),
+    };
+    let reset = client
+        .password_reset()
+        .find_unique(db::password_reset::token_hash::equals(hash_token(
+            &payload.token,
+        )))
+        .exec()
+        .await?
+        .ok_or_else(invalid)?;
+    if reset.used_at.is_some() || reset.expires_at < now() {
+        return Err(invalid());
+    }
+    let password = hash_password(&payload.new_password)?;
+    client
+        ._transaction()
+        .run(|client| async move {
+            let used = client
+                .password_reset()
+                .update_many(
+                    vec![
+                        db::password_reset::id::equals(reset.id),
+                        db::password_reset::used_at::equals(None),
+                    ],
+                    vec![db::password_reset::used_at::set(Some(now()))],
+                )
+                .exec()
+                .await?;
+            if used == 0 {
+                return Err(invalid());
+            }
+            client
+                .user()
+                .update(
+                    db::user::id::equals(reset.user_id),
+                    vec![db::user::password::set(password)],
+                )
+                .exec()
+                .await?;
+            revoke_user_sessions(&client, reset.user_id).await
+        })
+        .await?;
+    info!("user {} reset their password", reset.user_id);
+    Ok(())
+}
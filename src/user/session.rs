@@ -0,0 +1,166 @@
+use chrono::{DateTime, Duration, FixedOffset, Utc};
+use rand::{distributions::Alphanumeric, Rng};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::warn;
+
+use crate::{
+    db::{self, PrismaClient},
+    errors::{AppError, AppResult},
+    utils::{JwtClaims, KEYS},
+};
+
+/// lifetime of the jwt access token, kept short since it is only checked
+/// against the session table and not re-issued on every request
+pub const ACCESS_TOKEN_MINUTES: i64 = 15;
+pub const REFRESH_TOKEN_DAYS: i64 = 14;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TokenPair {
+    pub token: String,
+    pub refresh_token: String,
+}
+
+/// Random opaque token, used for refresh tokens and other one-time links.
+pub fn new_token() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(64)
+        .map(char::from)
+        .collect()
+}
+
+/// Only the digest of a token is persisted.
+pub fn hash_token(token: &str) -> String {
+    format!("{:X}", Sha256::digest(token))
+}
+
+pub fn now() -> DateTime<FixedOffset> {
+    Utc::now().into()
+}
+
+fn access_token(user: &db::user::Data, session_id: i32) -> AppResult<String> {
+    let exp = Utc::now() + Duration::minutes(ACCESS_TOKEN_MINUTES);
+    let claim = JwtClaims {
+        user_id: user.id,
+        company_id: user.company_id,
+        role_id: user.role_id,
+        session_id,
+        exp: exp.timestamp(),
+    };
+    Ok(jsonwebtoken::encode(
+        &jsonwebtoken::Header::default(),
+        &claim,
+        &KEYS.encoding,
+    )?)
+}
+
+pub async fn create_session(client: &PrismaClient, user: &db::user::Data) -> AppResult<TokenPair> {
+    let refresh_token = new_token();
+    let s = client
+        .session()
+        .create(
+            db::user::id::equals(user.id),
+            hash_token(&refresh_token),
+            (Utc::now() + Duration::days(REFRESH_TOKEN_DAYS)).into(),
+            vec![],
+        )
+        .exec()
+        .await?;
+    Ok(TokenPair {
+        token: access_token(user, s.id)?,
+        refresh_token,
+    })
+}
+
+/// Exchange a refresh token for a new token pair, the presented refresh token
+/// is replaced and can not be used again. A token presented twice at the same
+/// time has been replayed, the session is revoked and neither gets a pair.
+pub async fn rotate_session(client: &PrismaClient, refresh_token: &str) -> AppResult<TokenPair> {
+    let invalid = || AppError::Custom {
+        status_code: 401,
+        error: "invalid refresh token".to_string(),
+    };
+    let old_hash = hash_token(refresh_token);
+    let s = client
+        .session()
+        .find_unique(db::session::refresh_token_hash::equals(old_hash.clone()))
+        .exec()
+        .await?
+        .ok_or_else(invalid)?;
+    if s.revoked_at.is_some() || s.expires_at < now() {
+        return Err(invalid());
+    }
+    let user = client
+        .user()
+        .find_unique(db::user::id::equals(s.user_id))
+        .exec()
+        .await?
+        .ok_or_else(invalid)?;
+    if user.is_active != db::IsActive::Yes || user.deleted_at.is_some() {
+        revoke_session(client, s.id).await?;
+        return Err(AppError::Custom {
+            status_code: 403,
+            error: "user is not active".to_string(),
+        });
+    }
+    let refresh_token = new_token();
+    let rotated = client
+        .session()
+        .update_many(
+            vec![
+                db::session::id::equals(s.id),
+                db::session::refresh_token_hash::equals(old_hash),
+                db::session::revoked_at::equals(None),
+            ],
+            vec![
+                db::session::refresh_token_hash::set(hash_token(&refresh_token)),
+                db::session::expires_at::set(
+                    (Utc::now() + Duration::days(REFRESH_TOKEN_DAYS)).into(),
+                ),
+            ],
+        )
+        .exec()
+        .await?;
+    if rotated == 0 {
+        warn!("refresh token of session {} was reused", s.id);
+        revoke_session(client, s.id).await?;
+        return Err(invalid());
+    }
+    Ok(TokenPair {
+        token: access_token(&user, s.id)?,
+        refresh_token,
+    })
+}
+
+pub async fn revoke_session(client: &PrismaClient, session_id: i32) -> AppResult<()> {
+    client
+        .session()
+        .update_many(
+            vec![
+                db::session::id::equals(session_id),
+                db::session::revoked_at::equals(None),
+            ],
+            vec![db::session::revoked_at::set(Some(now()))],
+        )
+        .exec()
+        .await?;
+    Ok(())
+}
+
+/// Revoke every open session of a user, used when the user is deactivated or
+/// their role changes so that outstanding access tokens stop working.
+pub async fn revoke_user_sessions(client: &PrismaClient, user_id: i32) -> AppResult<()> {
+    client
+        .session()
+        .update_many(
+            vec![
+                db::session::user_id::equals(user_id),
+                db::session::revoked_at::equals(None),
+            ],
+            vec![db::session::revoked_at::set(Some(now()))],
+        )
+        .exec()
+        .await?;
+    Ok(())
+}
@@ -1,4 +1,6 @@
+use std::collections::HashMap;
 use std::fmt::Debug;
+use std::sync::RwLock;
 
 use anyhow::{bail, Result};
 use async_trait::async_trait;
@@ -9,13 +11,15 @@ use axum::{
     response::{IntoResponse, Response},
     RequestPartsExt,
 };
+use chrono::Utc;
 use jsonwebtoken::{decode, DecodingKey, EncodingKey, Validation};
 use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
+use subtle::ConstantTimeEq;
 
 use crate::{
     db::{self, PrivilegeType},
-    errors::AppError,
+    errors::{AppError, AppResult},
     DB,
 };
 
@@ -43,44 +47,86 @@ pub struct JwtClaims {
     pub user_id: i32,
     pub company_id: i32,
     pub role_id: i32,
+    pub session_id: i32,
     pub exp: i64,
 }
 
+/// Privileges of each role keyed by `role_id`, filled lazily by
+/// `check_module_privilige` and invalidated whenever a role changes.
+#[derive(Default)]
+struct PrivilegeCache {
+    /// bumped by every invalidation, a load that saw another generation may
+    /// have read privileges which are already stale
+    generation: u64,
+    roles: HashMap<i32, Vec<(db::Module, PrivilegeType)>>,
+}
+
+static ROLE_PRIVILEGES: Lazy<RwLock<PrivilegeCache>> = Lazy::new(Default::default);
+
+pub fn invalidate_role_privileges(role_id: i32) {
+    let mut cache = ROLE_PRIVILEGES.write().unwrap();
+    cache.generation += 1;
+    cache.roles.remove(&role_id);
+}
+
+async fn role_privileges(role_id: i32) -> Result<Vec<(db::Module, PrivilegeType)>> {
+    let generation = {
+        let cache = ROLE_PRIVILEGES.read().unwrap();
+        if let Some(p) = cache.roles.get(&role_id) {
+            return Ok(p.clone());
+        }
+        cache.generation
+    };
+    let client = DB.get().unwrap();
+    let p: Vec<_> = client
+        .privilege()
+        .find_many(vec![
+            db::privilege::role_id::equals(role_id),
+            db::privilege::role::is(vec![db::role::deleted_at::equals(None)]),
+        ])
+        .exec()
+        .await?
+        .into_iter()
+        .map(|p| (p.module, p.privilege_type))
+        .collect();
+    let mut cache = ROLE_PRIVILEGES.write().unwrap();
+    if cache.generation == generation {
+        cache.roles.insert(role_id, p.clone());
+    }
+    Ok(p)
+}
+
+fn privilege_rank(privilege: PrivilegeType) -> u8 {
+    match privilege {
+        PrivilegeType::None => 0,
+        PrivilegeType::View => 1,
+        PrivilegeType::Edit => 2,
+    }
+}
+
+/// Whether the privileges granted on a module allow `privilege`.
+fn grants(granted: &[PrivilegeType], privilege: PrivilegeType) -> bool {
+    !granted.contains(&PrivilegeType::None)
+        && granted
+            .iter()
+            .any(|p| privilege_rank(*p) >= privilege_rank(privilege))
+}
+
 impl JwtClaims {
+    /// EDIT implies VIEW, an explicit NONE on the module always denies.
     pub async fn check_module_privilige(
         &self,
         module: db::Module,
         privilege: db::PrivilegeType,
     ) -> Result<()> {
-        let client = DB.get().unwrap();
-        let r = client
-            .role()
-            .find_unique(db::role::id::equals(self.role_id))
-            .exec()
-            .await?;
-        if let Some(role) = r {
-            if let Some(rpv) = role.role_privileges {
-                for p in rpv {
-                    if p.module == module && p.privilege_type == privilege {
-                        match privilege {
-                            PrivilegeType::Edit => return Ok(()),
-                            PrivilegeType::View => match p.privilege_type {
-                                PrivilegeType::Edit => bail!(
-                                    "you are not allowed to {} in {}",
-                                    privilege.to_string(),
-                                    module.to_string()
-                                ),
-                                _ => return Ok(()),
-                            },
-                            _ => bail!(
-                                "you are not allowed to {} in {}",
-                                privilege.to_string(),
-                                module.to_string()
-                            ),
-                        }
-                    }
-                }
-            }
+        let granted: Vec<_> = role_privileges(self.role_id)
+            .await?
+            .into_iter()
+            .filter(|(m, _)| *m == module)
+            .map(|(_, p)| p)
+            .collect();
+        if grants(&granted, privilege) {
+            return Ok(());
         }
         bail!(
             "you are not allowed to {} in {}",
@@ -90,6 +136,45 @@ impl JwtClaims {
     }
 }
 
+/// A field of an update payload that needs a privilege of its own.
+pub struct GuardedField {
+    pub name: &'static str,
+    pub module: db::Module,
+    pub privilege: PrivilegeType,
+}
+
+/// Implemented by update payloads whose fields need more than the privilege
+/// required by the handler, see `field_policy!`.
+pub trait FieldPolicy {
+    /// the guarded fields which are set in this payload
+    fn guarded_fields(&self) -> Vec<GuardedField>;
+}
+
+impl JwtClaims {
+    /// Fails with a 403 listing every set field the caller may not change.
+    pub async fn check_field_privileges(&self, payload: &impl FieldPolicy) -> AppResult<()> {
+        let mut forbidden = vec![];
+        for f in payload.guarded_fields() {
+            if self
+                .check_module_privilige(f.module, f.privilege)
+                .await
+                .is_err()
+            {
+                forbidden.push(f.name.to_string());
+            }
+        }
+        if forbidden.is_empty() {
+            Ok(())
+        } else {
+            Err(AppError::ForbiddenFields(forbidden))
+        }
+    }
+}
+
+pub fn subscription_expired(company: &db::company::Data) -> bool {
+    company.expiration_date < Utc::now()
+}
+
 #[allow(unused)]
 #[derive(Debug)]
 pub enum AuthError {
@@ -97,6 +182,9 @@ pub enum AuthError {
     MissingCredentials,
     TokenCreation,
     InvalidToken,
+    SessionRevoked,
+    SubscriptionExpired,
+    Internal,
 }
 
 impl IntoResponse for AuthError {
@@ -106,6 +194,12 @@ impl IntoResponse for AuthError {
             AuthError::MissingCredentials => (StatusCode::UNAUTHORIZED, "Missing credentials"),
             AuthError::TokenCreation => (StatusCode::INTERNAL_SERVER_ERROR, "Token creation error"),
             AuthError::InvalidToken => (StatusCode::BAD_REQUEST, "Invalid token"),
+            AuthError::SessionRevoked => (StatusCode::UNAUTHORIZED, "Session has been revoked"),
+            AuthError::SubscriptionExpired => (
+                StatusCode::PAYMENT_REQUIRED,
+                "Company subscription has expired",
+            ),
+            AuthError::Internal => (StatusCode::INTERNAL_SERVER_ERROR, "Internal error"),
         };
         AppError::Custom {
             status_code: status.as_u16(),
@@ -135,21 +229,111 @@ where
         let token_data =
             decode::<JwtClaims>(bearer.token(), &KEYS.decoding, &Validation::default())
                 .map_err(|_| AuthError::WrongCredentials)?;
+        let claims = token_data.claims;
+
+        // Make sure the session backing the token is still alive
+        let client = DB.get().unwrap();
+        let session = client
+            .session()
+            .find_unique(db::session::id::equals(claims.session_id))
+            .with(db::session::user::fetch().with(db::user::company::fetch()))
+            .exec()
+            .await
+            .map_err(|e| {
+                tracing::error!("failed to load session {}: {}", claims.session_id, e);
+                AuthError::Internal
+            })?
+            .ok_or(AuthError::SessionRevoked)?;
+        if session.revoked_at.is_some() || session.user_id != claims.user_id {
+            return Err(AuthError::SessionRevoked);
+        }
+        let user = session.user().map_err(|_| AuthError::Internal)?;
+        if user.is_active != db::IsActive::Yes
+            || user.role_id != claims.role_id
+            || user.deleted_at.is_some()
+        {
+            return Err(AuthError::SessionRevoked);
+        }
+        let company = user.company().map_err(|_| AuthError::Internal)?;
+        if subscription_expired(company) {
+            return Err(AuthError::SubscriptionExpired);
+        }
 
-        Ok(token_data.claims)
+        Ok(claims)
     }
 }
 
 #[macro_export]
-macro_rules! cmp_company_id {
-    ($e:expr, $c:expr) => {
-        if $e.company_id == $c.company_id {
-            CommonResponse::json_data($e)
-        } else {
-            Err(AppError::Custom {
-                status_code: 400,
-                error: "Invalid request".to_string(),
-            })
+macro_rules! field_policy {
+    ($t:ty { $($field:ident => ($module:ident, $privilege:ident)),* $(,)? }) => {
+        impl $crate::utils::FieldPolicy for $t {
+            fn guarded_fields(&self) -> Vec<$crate::utils::GuardedField> {
+                let mut fields = vec![];
+                $(
+                    if self.$field.is_some() {
+                        fields.push($crate::utils::GuardedField {
+                            name: stringify!($field),
+                            module: $crate::db::Module::$module,
+                            privilege: $crate::db::PrivilegeType::$privilege,
+                        });
+                    }
+                )*
+                fields
+            }
         }
     };
 }
+
+/// Extractor for platform level operations, i.e. managing the tenants
+/// themselves, authenticated by the `PLATFORM_API_KEY` shared secret.
+pub struct PlatformAdmin;
+
+#[async_trait]
+impl<S> FromRequestParts<S> for PlatformAdmin
+where
+    S: Send + Sync,
+{
+    type Rejection = AuthError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let expected = std::env::var("PLATFORM_API_KEY").map_err(|_| AuthError::InvalidToken)?;
+        let key = parts
+            .headers
+            .get("x-platform-key")
+            .and_then(|v| v.to_str().ok())
+            .ok_or(AuthError::MissingCredentials)?;
+        if expected.is_empty() || !bool::from(key.as_bytes().ct_eq(expected.as_bytes())) {
+            return Err(AuthError::WrongCredentials);
+        }
+        Ok(PlatformAdmin)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn privilege_rank_orders_privileges() {
+        assert!(privilege_rank(PrivilegeType::None) < privilege_rank(PrivilegeType::View));
+        assert!(privilege_rank(PrivilegeType::View) < privilege_rank(PrivilegeType::Edit));
+    }
+
+    #[test]
+    fn edit_implies_view() {
+        assert!(grants(&[PrivilegeType::Edit], PrivilegeType::View));
+        assert!(grants(&[PrivilegeType::Edit], PrivilegeType::Edit));
+        assert!(grants(&[PrivilegeType::View], PrivilegeType::View));
+        assert!(!grants(&[PrivilegeType::View], PrivilegeType::Edit));
+    }
+
+    #[test]
+    fn none_always_denies() {
+        assert!(!grants(&[], PrivilegeType::View));
+        assert!(!grants(&[PrivilegeType::None], PrivilegeType::None));
+        assert!(!grants(
+            &[PrivilegeType::Edit, PrivilegeType::None],
+            PrivilegeType::View
+        ));
+    }
+}
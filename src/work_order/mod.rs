@@ -0,0 +1,990 @@
+use axum::debug_handler;
+use axum::extract::{Path, Query};
+use axum::Json;
+use chrono::{DateTime, FixedOffset, Utc};
+use prisma_client_rust::{raw, Direction, PrismaValue};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::assets::tree::asset_tree;
+use crate::db::{self, PrismaClient};
+use crate::errors::{AppError, AppResult, CommonResponse};
+use crate::maintainance_request::{check_open, follow_up, update_unmoved, MrAction};
+use crate::repo::{check_references, page_size, Page, SortOrder, Tenant};
+use crate::utils::JwtClaims;
+use crate::DB;
+
+db::work_order::select! {
+    wo_out {
+        id
+        mr_id
+        wo_name
+        wo_description
+        wo_state
+        planned_start
+        planned_end
+        started_at
+        completed_at
+        completion_notes
+        created_at
+        updated_at
+        asset: select {
+            id
+            asset_code
+            asset_name
+        }
+        assignees: select {
+            id
+            username
+            nickname
+        }
+        tasks: select {
+            id
+            description
+            done_at
+        }
+        labour: select {
+            id
+            user: select {
+                id
+                username
+                nickname
+            }
+            started_at
+            minutes
+            note
+        }
+        parts: select {
+            id
+            part_code
+            part_name
+            quantity
+            unit
+        }
+    }
+}
+
+fn not_found(label: &str) -> AppError {
+    AppError::Custom {
+        status_code: 404,
+        error: format!("{} not found", label),
+    }
+}
+
+/// Completed and cancelled work orders are kept as they were finished.
+fn check_editable(wo: &db::work_order::Data) -> AppResult<()> {
+    match wo.wo_state {
+        db::WoState::Completed | db::WoState::Cancelled => Err(AppError::Custom {
+            status_code: 409,
+            error: format!("work order is {:?} and can not be changed", wo.wo_state).to_lowercase(),
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// Every id has to be a user of the company.
+async fn check_users(tenant: Tenant<'_>, field: &'static str, user_ids: &[i32]) -> AppResult<()> {
+    let mut unique = user_ids.to_vec();
+    unique.sort_unstable();
+    unique.dedup();
+    let count = tenant
+        .user()
+        .count(vec![db::user::id::in_vec(unique.clone())])
+        .exec()
+        .await?;
+    check_references(vec![(field, count == unique.len() as i64)])
+}
+
+fn user_links(user_ids: Vec<i32>) -> Vec<db::user::UniqueWhereParam> {
+    user_ids.into_iter().map(db::user::id::equals).collect()
+}
+
+fn check_planned(
+    start: Option<DateTime<FixedOffset>>,
+    end: Option<DateTime<FixedOffset>>,
+) -> AppResult<()> {
+    match (start, end) {
+        (Some(start), Some(end)) if end < start => {
+            Err(AppError::InvalidFields(vec!["planned_end".to_string()]))
+        }
+        _ => Ok(()),
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WoListQuery {
+    pub cursor: Option<i32>,
+    pub limit: Option<i64>,
+    #[serde(default)]
+    pub order: SortOrder,
+    pub mr_id: Option<i32>,
+    pub asset_id: Option<i32>,
+    pub state: Option<db::WoState>,
+    pub assignee_id: Option<i32>,
+    pub planned_from: Option<DateTime<FixedOffset>>,
+    pub planned_to: Option<DateTime<FixedOffset>>,
+}
+
+impl WoListQuery {
+    fn params(&self) -> Vec<db::work_order::WhereParam> {
+        use db::work_order as wo;
+        let mut filters = vec![];
+        if let Some(id) = self.mr_id {
+            filters.push(wo::mr_id::equals(Some(id)));
+        }
+        if let Some(id) = self.asset_id {
+            filters.push(wo::asset_id::equals(id));
+        }
+        if let Some(state) = self.state {
+            filters.push(wo::wo_state::equals(state));
+        }
+        if let Some(id) = self.assignee_id {
+            filters.push(wo::assignees::some(vec![db::user::id::equals(id)]));
+        }
+        if let Some(from) = self.planned_from {
+            filters.push(wo::planned_start::gte(from));
+        }
+        if let Some(to) = self.planned_to {
+            filters.push(wo::planned_start::lt(to));
+        }
+        filters
+    }
+}
+
+#[debug_handler]
+pub async fn list_wos(
+    c: JwtClaims,
+    Query(q): Query<WoListQuery>,
+) -> AppResult<Json<CommonResponse<Page<wo_out::Data>>>> {
+    c.check_module_privilige(db::Module::WorkOrder, db::PrivilegeType::View)
+        .await?;
+    let client = DB.get().unwrap();
+    let tenant = c.tenant(client);
+    let size = page_size(q.limit);
+    let total = tenant.work_order().count(q.params()).exec().await?;
+    let mut query = tenant
+        .work_order()
+        .find_many(q.params())
+        .order_by(db::work_order::id::order(Direction::from(q.order)))
+        .take(size + 1);
+    if let Some(cursor) = q.cursor {
+        query = query.cursor(db::work_order::id::equals(cursor)).skip(1);
+    }
+    let wos = query.select(wo_out::select()).exec().await?;
+    CommonResponse::json_data(Page::new(wos, total, size, |w| w.id))
+}
+
+#[debug_handler]
+pub async fn get_wo(
+    Path(id): Path<i32>,
+    c: JwtClaims,
+) -> AppResult<Json<CommonResponse<wo_out::Data>>> {
+    c.check_module_privilige(db::Module::WorkOrder, db::PrivilegeType::View)
+        .await?;
+    let client = DB.get().unwrap();
+    let wo = c
+        .tenant(client)
+        .work_order()
+        .find_by_id(id)
+        .select(wo_out::select())
+        .exec()
+        .await?;
+    match wo {
+        Some(wo) => CommonResponse::json_data(wo),
+        _ => Err(not_found("work order")),
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateWoInfo {
+    /// the maintenance request the work order fixes
+    pub mr_id: Option<i32>,
+    /// the asset of the maintenance request or one of its components, the
+    /// asset of the maintenance request itself when absent
+    pub asset_id: Option<i32>,
+    pub wo_name: String,
+    pub wo_description: String,
+    pub planned_start: Option<DateTime<FixedOffset>>,
+    pub planned_end: Option<DateTime<FixedOffset>>,
+    #[serde(default)]
+    pub assignee_ids: Vec<i32>,
+    /// descriptions of the tasks to do, in order
+    #[serde(default)]
+    pub tasks: Vec<String>,
+}
+
+/// A work order spawned from an open maintenance request acknowledges it,
+/// unless the workflow wants it to go through other statuses first.
+#[debug_handler]
+pub async fn create_wo(
+    c: JwtClaims,
+    Json(payload): Json<CreateWoInfo>,
+) -> AppResult<Json<CommonResponse<wo_out::Data>>> {
+    c.check_module_privilige(db::Module::WorkOrder, db::PrivilegeType::Edit)
+        .await?;
+    let client = DB.get().unwrap();
+    let tenant = c.tenant(client);
+    let mr = match payload.mr_id {
+        Some(id) => tenant.maintainance_request().find_by_id(id).exec().await?,
+        None => None,
+    };
+    check_references(vec![
+        ("mr_id", payload.mr_id.is_none() || mr.is_some()),
+        ("asset_id", tenant.asset().owns_opt(payload.asset_id).await?),
+    ])?;
+    if let Some(mr) = &mr {
+        check_open(mr)?;
+    }
+    let asset_id = match (payload.asset_id, &mr) {
+        // the asset of the mr or one of its components
+        (Some(id), Some(mr)) if id != mr.asset_id => {
+            let ancestors = asset_tree(tenant).await?.ancestors(id);
+            if !ancestors.contains(&mr.asset_id) {
+                return Err(AppError::InvalidReferences(vec!["asset_id".to_string()]));
+            }
+            id
+        }
+        (Some(id), _) => id,
+        (None, Some(mr)) => mr.asset_id,
+        (None, None) => return Err(AppError::InvalidFields(vec!["asset_id".to_string()])),
+    };
+    check_planned(payload.planned_start, payload.planned_end)?;
+    check_users(tenant, "assignee_ids", &payload.assignee_ids).await?;
+    let acknowledge = match &mr {
+        Some(mr) => follow_up(tenant, c.role_id, mr, MrAction::Acknowledge).await?,
+        None => None,
+    };
+    let company_id = c.company_id;
+    let wo = client
+        ._transaction()
+        .run(|client| async move {
+            let tenant = Tenant::new(&client, company_id);
+            let wo = tenant
+                .work_order()
+                .create(|wos, company| {
+                    wos.create(
+                        company,
+                        db::asset::id::equals(asset_id),
+                        payload.wo_name,
+                        payload.wo_description,
+                        vec![
+                            db::work_order::mr_id::set(payload.mr_id),
+                            db::work_order::planned_start::set(payload.planned_start),
+                            db::work_order::planned_end::set(payload.planned_end),
+                            db::work_order::assignees::connect(user_links(payload.assignee_ids)),
+                        ],
+                    )
+                })
+                .exec()
+                .await?;
+            for description in payload.tasks {
+                client
+                    .wo_task()
+                    .create(db::work_order::id::equals(wo.id), description, vec![])
+                    .exec()
+                    .await?;
+            }
+            if let (Some(mr), Some(params)) = (mr, acknowledge) {
+                update_unmoved(tenant, &mr, params).await?;
+            }
+            Ok::<_, AppError>(
+                client
+                    .work_order()
+                    .find_unique(db::work_order::id::equals(wo.id))
+                    .select(wo_out::select())
+                    .exec()
+                    .await?,
+            )
+        })
+        .await?;
+    CommonResponse::json_data(wo.ok_or_else(|| not_found("work order"))?)
+}
+
+db::work_order::partial!(
+    UpdateWoInfo {
+        wo_name
+        wo_description
+        planned_start
+        planned_end
+    }
+);
+
+#[debug_handler]
+pub async fn update_wo(
+    Path(id): Path<i32>,
+    c: JwtClaims,
+    Json(payload): Json<UpdateWoInfo>,
+) -> AppResult<Json<CommonResponse<wo_out::Data>>> {
+    c.check_module_privilige(db::Module::WorkOrder, db::PrivilegeType::Edit)
+        .await?;
+    let client = DB.get().unwrap();
+    let tenant = c.tenant(client);
+    let wo = tenant.work_order().get(id).await?;
+    check_editable(&wo)?;
+    check_planned(
+        payload.planned_start.unwrap_or(wo.planned_start),
+        payload.planned_end.unwrap_or(wo.planned_end),
+    )?;
+    CommonResponse::json_data(
+        tenant
+            .work_order()
+            .update(id, payload.to_params())
+            .await?
+            .select(wo_out::select())
+            .exec()
+            .await?,
+    )
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WoAssigneesInfo {
+    /// replaces the assignees of the work order
+    pub user_ids: Vec<i32>,
+}
+
+#[debug_handler]
+pub async fn set_wo_assignees(
+    Path(id): Path<i32>,
+    c: JwtClaims,
+    Json(payload): Json<WoAssigneesInfo>,
+) -> AppResult<Json<CommonResponse<wo_out::Data>>> {
+    c.check_module_privilige(db::Module::WorkOrder, db::PrivilegeType::Edit)
+        .await?;
+    let client = DB.get().unwrap();
+    let tenant = c.tenant(client);
+    check_editable(&tenant.work_order().get(id).await?)?;
+    check_users(tenant, "user_ids", &payload.user_ids).await?;
+    CommonResponse::json_data(
+        tenant
+            .work_order()
+            .update(
+                id,
+                vec![db::work_order::assignees::set(user_links(payload.user_ids))],
+            )
+            .await?
+            .select(wo_out::select())
+            .exec()
+            .await?,
+    )
+}
+
+/// Finished work orders are kept as the record of the work done.
+#[debug_handler]
+pub async fn delete_wo(
+    Path(id): Path<i32>,
+    c: JwtClaims,
+) -> AppResult<Json<CommonResponse<wo_out::Data>>> {
+    c.check_module_privilige(db::Module::WorkOrder, db::PrivilegeType::Edit)
+        .await?;
+    let client = DB.get().unwrap();
+    let wo = c.tenant(client).work_order().get(id).await?;
+    check_editable(&wo)?;
+    let (company_id, role_id) = (c.company_id, c.role_id);
+    let deleted = client
+        ._transaction()
+        .run(|client| async move {
+            let tenant = Tenant::new(&client, company_id);
+            let deleted = tenant
+                .work_order()
+                .soft_delete(id)
+                .await?
+                .select(wo_out::select())
+                .exec()
+                .await?;
+            // the work orders left may all be done now
+            if let Some(mr_id) = wo.mr_id {
+                close_finished_mr(&client, company_id, role_id, mr_id).await?;
+            }
+            Ok::<_, AppError>(deleted)
+        })
+        .await?;
+    CommonResponse::json_data(deleted)
+}
+
+/// Steps of the work order lifecycle.
+#[derive(Debug, Clone, Copy)]
+enum WoAction {
+    Start,
+    Hold,
+    Complete,
+    Cancel,
+}
+
+impl WoAction {
+    fn done(self) -> &'static str {
+        match self {
+            WoAction::Start => "started",
+            WoAction::Hold => "put on hold",
+            WoAction::Complete => "completed",
+            WoAction::Cancel => "cancelled",
+        }
+    }
+
+    /// The changes the action makes to the work order, fails with a 409 when
+    /// it can not be taken from the current state.
+    fn params(self, wo: &db::work_order::Data) -> AppResult<Vec<db::work_order::SetParam>> {
+        use db::work_order as w;
+        use db::WoState::*;
+        let now: DateTime<FixedOffset> = Utc::now().into();
+        let params = match (self, wo.wo_state) {
+            (WoAction::Start, Planned | OnHold) => vec![
+                w::wo_state::set(InProgress),
+                w::started_at::set(wo.started_at.or(Some(now))),
+            ],
+            (WoAction::Hold, InProgress) => vec![w::wo_state::set(OnHold)],
+            (WoAction::Complete, InProgress) => {
+                vec![w::wo_state::set(Completed), w::completed_at::set(Some(now))]
+            }
+            (WoAction::Cancel, Planned | InProgress | OnHold) => vec![w::wo_state::set(Cancelled)],
+            (action, state) => {
+                return Err(AppError::Custom {
+                    status_code: 409,
+                    error: format!("work order is {:?} and can not be {}", state, action.done())
+                        .to_lowercase(),
+                })
+            }
+        };
+        Ok(params)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct LockedMr {
+    #[allow(dead_code)]
+    id: i32,
+}
+
+/// Close the maintenance request once none of its work orders is left to do
+/// and at least one of them was completed.
+async fn close_finished_mr(
+    client: &PrismaClient,
+    company_id: i32,
+    role_id: i32,
+    mr_id: i32,
+) -> AppResult<()> {
+    // work orders of the mr finishing at the same time take turns, the last
+    // one sees the others done
+    let _: Vec<LockedMr> = client
+        ._query_raw(raw!(
+            "SELECT id FROM \"MaintainanceRequest\" WHERE id = {} FOR UPDATE",
+            PrismaValue::Int(mr_id as i64)
+        ))
+        .exec()
+        .await?;
+    let tenant = Tenant::new(client, company_id);
+    let wos = tenant
+        .work_order()
+        .find_many(vec![db::work_order::mr_id::equals(Some(mr_id))])
+        .exec()
+        .await?;
+    let finished = wos
+        .iter()
+        .all(|w| matches!(w.wo_state, db::WoState::Completed | db::WoState::Cancelled));
+    let completed = wos.iter().any(|w| w.wo_state == db::WoState::Completed);
+    if !finished || !completed {
+        return Ok(());
+    }
+    if let Some(mr) = tenant
+        .maintainance_request()
+        .find_by_id(mr_id)
+        .exec()
+        .await?
+    {
+        // an mr closed or cancelled by hand stays as it is
+        if let Some(params) = follow_up(tenant, role_id, &mr, MrAction::Close).await? {
+            update_unmoved(tenant, &mr, params).await?;
+            info!("mr {} closed as its work orders are done", mr_id);
+        }
+    }
+    Ok(())
+}
+
+async fn change_wo_state(
+    id: i32,
+    c: JwtClaims,
+    action: WoAction,
+    mut params: Vec<db::work_order::SetParam>,
+) -> AppResult<Json<CommonResponse<wo_out::Data>>> {
+    c.check_module_privilige(db::Module::WorkOrder, db::PrivilegeType::Edit)
+        .await?;
+    let client = DB.get().unwrap();
+    let (company_id, role_id) = (c.company_id, c.role_id);
+    let wo = client
+        ._transaction()
+        .run(|client| async move {
+            let tenant = Tenant::new(&client, company_id);
+            let wo = tenant.work_order().get(id).await?;
+            params.extend(action.params(&wo)?);
+            // only while no other request moved the work order meanwhile
+            let updated = tenant
+                .work_order()
+                .update_many(
+                    vec![
+                        db::work_order::id::equals(id),
+                        db::work_order::wo_state::equals(wo.wo_state),
+                    ],
+                    params,
+                )
+                .exec()
+                .await?;
+            if updated == 0 {
+                return Err(AppError::Custom {
+                    status_code: 409,
+                    error: "work order was changed meanwhile, try again".to_string(),
+                });
+            }
+            if let (WoAction::Complete | WoAction::Cancel, Some(mr_id)) = (action, wo.mr_id) {
+                close_finished_mr(&client, company_id, role_id, mr_id).await?;
+            }
+            tenant
+                .work_order()
+                .find_by_id(id)
+                .select(wo_out::select())
+                .exec()
+                .await?
+                .ok_or_else(|| not_found("work order"))
+        })
+        .await?;
+    info!(
+        "work order {} of company {} {}",
+        id,
+        company_id,
+        action.done()
+    );
+    CommonResponse::json_data(wo)
+}
+
+#[debug_handler]
+pub async fn start_wo(
+    Path(id): Path<i32>,
+    c: JwtClaims,
+) -> AppResult<Json<CommonResponse<wo_out::Data>>> {
+    change_wo_state(id, c, WoAction::Start, vec![]).await
+}
+
+#[debug_handler]
+pub async fn hold_wo(
+    Path(id): Path<i32>,
+    c: JwtClaims,
+) -> AppResult<Json<CommonResponse<wo_out::Data>>> {
+    change_wo_state(id, c, WoAction::Hold, vec![]).await
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CompleteWoInfo {
+    pub completion_notes: Option<String>,
+}
+
+#[debug_handler]
+pub async fn complete_wo(
+    Path(id): Path<i32>,
+    c: JwtClaims,
+    Json(payload): Json<CompleteWoInfo>,
+) -> AppResult<Json<CommonResponse<wo_out::Data>>> {
+    let params = vec![db::work_order::completion_notes::set(
+        payload.completion_notes,
+    )];
+    change_wo_state(id, c, WoAction::Complete, params).await
+}
+
+#[debug_handler]
+pub async fn cancel_wo(
+    Path(id): Path<i32>,
+    c: JwtClaims,
+) -> AppResult<Json<CommonResponse<wo_out::Data>>> {
+    change_wo_state(id, c, WoAction::Cancel, vec![]).await
+}
+
+/// The tasks, labour and parts of a work order only change while the work
+/// order is still being planned or worked on. Meant to run in the transaction
+/// making the change, the work order is held until it ends so that it can not
+/// be completed or cancelled meanwhile.
+async fn editable_wo(tenant: Tenant<'_>, id: i32) -> AppResult<()> {
+    let held = tenant
+        .work_order()
+        .update_many(
+            vec![
+                db::work_order::id::equals(id),
+                db::work_order::wo_state::in_vec(vec![
+                    db::WoState::Planned,
+                    db::WoState::InProgress,
+                    db::WoState::OnHold,
+                ]),
+            ],
+            vec![db::work_order::updated_at::set(Utc::now().into())],
+        )
+        .exec()
+        .await?;
+    if held == 0 {
+        // finished work orders never become editable again
+        check_editable(&tenant.work_order().get(id).await?)?;
+    }
+    Ok(())
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WoTaskInfo {
+    pub description: String,
+}
+
+#[debug_handler]
+pub async fn add_wo_task(
+    Path(id): Path<i32>,
+    c: JwtClaims,
+    Json(payload): Json<WoTaskInfo>,
+) -> AppResult<Json<CommonResponse<db::wo_task::Data>>> {
+    c.check_module_privilige(db::Module::WorkOrder, db::PrivilegeType::Edit)
+        .await?;
+    let client = DB.get().unwrap();
+    let company_id = c.company_id;
+    let task = client
+        ._transaction()
+        .run(|client| async move {
+            editable_wo(Tenant::new(&client, company_id), id).await?;
+            Ok::<_, AppError>(
+                client
+                    .wo_task()
+                    .create(db::work_order::id::equals(id), payload.description, vec![])
+                    .exec()
+                    .await?,
+            )
+        })
+        .await?;
+    CommonResponse::json_data(task)
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UpdateWoTaskInfo {
+    pub description: Option<String>,
+    pub done: Option<bool>,
+}
+
+#[debug_handler]
+pub async fn update_wo_task(
+    Path((id, task_id)): Path<(i32, i32)>,
+    c: JwtClaims,
+    Json(payload): Json<UpdateWoTaskInfo>,
+) -> AppResult<Json<CommonResponse<db::wo_task::Data>>> {
+    c.check_module_privilige(db::Module::WorkOrder, db::PrivilegeType::Edit)
+        .await?;
+    let client = DB.get().unwrap();
+    let company_id = c.company_id;
+    let task = client
+        ._transaction()
+        .run(|client| async move {
+            editable_wo(Tenant::new(&client, company_id), id).await?;
+            let task = client
+                .wo_task()
+                .find_first(vec![
+                    db::wo_task::id::equals(task_id),
+                    db::wo_task::work_order_id::equals(id),
+                ])
+                .exec()
+                .await?
+                .ok_or_else(|| not_found("task"))?;
+            let mut params = vec![];
+            if let Some(description) = payload.description {
+                params.push(db::wo_task::description::set(description));
+            }
+            match payload.done {
+                Some(true) if task.done_at.is_none() => {
+                    params.push(db::wo_task::done_at::set(Some(Utc::now().into())))
+                }
+                Some(false) => params.push(db::wo_task::done_at::set(None)),
+                _ => {}
+            }
+            Ok::<_, AppError>(
+                client
+                    .wo_task()
+                    .update(db::wo_task::id::equals(task_id), params)
+                    .exec()
+                    .await?,
+            )
+        })
+        .await?;
+    CommonResponse::json_data(task)
+}
+
+#[debug_handler]
+pub async fn delete_wo_task(
+    Path((id, task_id)): Path<(i32, i32)>,
+    c: JwtClaims,
+) -> AppResult<Json<CommonResponse<db::wo_task::Data>>> {
+    c.check_module_privilige(db::Module::WorkOrder, db::PrivilegeType::Edit)
+        .await?;
+    let client = DB.get().unwrap();
+    let company_id = c.company_id;
+    let task = client
+        ._transaction()
+        .run(|client| async move {
+            editable_wo(Tenant::new(&client, company_id), id).await?;
+            client
+                .wo_task()
+                .find_first(vec![
+                    db::wo_task::id::equals(task_id),
+                    db::wo_task::work_order_id::equals(id),
+                ])
+                .exec()
+                .await?
+                .ok_or_else(|| not_found("task"))?;
+            Ok::<_, AppError>(
+                client
+                    .wo_task()
+                    .delete(db::wo_task::id::equals(task_id))
+                    .exec()
+                    .await?,
+            )
+        })
+        .await?;
+    CommonResponse::json_data(task)
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WoLabourInfo {
+    /// the user who did the work, the caller when absent
+    pub user_id: Option<i32>,
+    pub started_at: DateTime<FixedOffset>,
+    pub minutes: i32,
+    pub note: Option<String>,
+}
+
+#[debug_handler]
+pub async fn add_wo_labour(
+    Path(id): Path<i32>,
+    c: JwtClaims,
+    Json(payload): Json<WoLabourInfo>,
+) -> AppResult<Json<CommonResponse<db::wo_labour::Data>>> {
+    c.check_module_privilige(db::Module::WorkOrder, db::PrivilegeType::Edit)
+        .await?;
+    let client = DB.get().unwrap();
+    let user_id = payload.user_id.unwrap_or(c.user_id);
+    check_references(vec![(
+        "user_id",
+        c.tenant(client).user().owns(user_id).await?,
+    )])?;
+    if payload.minutes <= 0 {
+        return Err(AppError::InvalidFields(vec!["minutes".to_string()]));
+    }
+    let company_id = c.company_id;
+    let labour = client
+        ._transaction()
+        .run(|client| async move {
+            editable_wo(Tenant::new(&client, company_id), id).await?;
+            Ok::<_, AppError>(
+                client
+                    .wo_labour()
+                    .create(
+                        db::work_order::id::equals(id),
+                        db::user::id::equals(user_id),
+                        payload.started_at,
+                        payload.minutes,
+                        vec![db::wo_labour::note::set(payload.note)],
+                    )
+                    .exec()
+                    .await?,
+            )
+        })
+        .await?;
+    CommonResponse::json_data(labour)
+}
+
+#[debug_handler]
+pub async fn delete_wo_labour(
+    Path((id, labour_id)): Path<(i32, i32)>,
+    c: JwtClaims,
+) -> AppResult<Json<CommonResponse<db::wo_labour::Data>>> {
+    c.check_module_privilige(db::Module::WorkOrder, db::PrivilegeType::Edit)
+        .await?;
+    let client = DB.get().unwrap();
+    let company_id = c.company_id;
+    let labour = client
+        ._transaction()
+        .run(|client| async move {
+            editable_wo(Tenant::new(&client, company_id), id).await?;
+            client
+                .wo_labour()
+                .find_first(vec![
+                    db::wo_labour::id::equals(labour_id),
+                    db::wo_labour::work_order_id::equals(id),
+                ])
+                .exec()
+                .await?
+                .ok_or_else(|| not_found("labour entry"))?;
+            Ok::<_, AppError>(
+                client
+                    .wo_labour()
+                    .delete(db::wo_labour::id::equals(labour_id))
+                    .exec()
+                    .await?,
+            )
+        })
+        .await?;
+    CommonResponse::json_data(labour)
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WoPartInfo {
+    pub part_code: String,
+    pub part_name: String,
+    pub quantity: f64,
+    pub unit: Option<String>,
+}
+
+#[debug_handler]
+pub async fn add_wo_part(
+    Path(id): Path<i32>,
+    c: JwtClaims,
+    Json(payload): Json<WoPartInfo>,
+) -> AppResult<Json<CommonResponse<db::wo_part::Data>>> {
+    c.check_module_privilige(db::Module::WorkOrder, db::PrivilegeType::Edit)
+        .await?;
+    if payload.quantity <= 0.0 {
+        return Err(AppError::InvalidFields(vec!["quantity".to_string()]));
+    }
+    let client = DB.get().unwrap();
+    let company_id = c.company_id;
+    let part = client
+        ._transaction()
+        .run(|client| async move {
+            editable_wo(Tenant::new(&client, company_id), id).await?;
+            Ok::<_, AppError>(
+                client
+                    .wo_part()
+                    .create(
+                        db::work_order::id::equals(id),
+                        payload.part_code,
+                        payload.part_name,
+                        payload.quantity,
+                        vec![db::wo_part::unit::set(payload.unit)],
+                    )
+                    .exec()
+                    .await?,
+            )
+        })
+        .await?;
+    CommonResponse::json_data(part)
+}
+
+#[debug_handler]
+pub async fn delete_wo_part(
+    Path((id, part_id)): Path<(i32, i32)>,
+    c: JwtClaims,
+) -> AppResult<Json<CommonResponse<db::wo_part::Data>>> {
+    c.check_module_privilige(db::Module::WorkOrder, db::PrivilegeType::Edit)
+        .await?;
+    let client = DB.get().unwrap();
+    let company_id = c.company_id;
+    let part = client
+        ._transaction()
+        .run(|client| async move {
+            editable_wo(Tenant::new(&client, company_id), id).await?;
+            client
+                .wo_part()
+                .find_first(vec![
+                    db::wo_part::id::equals(part_id),
+                    db::wo_part::work_order_id::equals(id),
+                ])
+                .exec()
+                .await?
+                .ok_or_else(|| not_found("part"))?;
+            Ok::<_, AppError>(
+                client
+                    .wo_part()
+                    .delete(db::wo_part::id::equals(part_id))
+                    .exec()
+                    .await?,
+            )
+        })
+        .await?;
+    CommonResponse::json_data(part)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use db::WoState::*;
+    use serde_json::json;
+
+    const STATES: [db::WoState; 5] = [Planned, InProgress, OnHold, Completed, Cancelled];
+    const ACTIONS: [WoAction; 4] = [
+        WoAction::Start,
+        WoAction::Hold,
+        WoAction::Complete,
+        WoAction::Cancel,
+    ];
+
+    fn work_order(wo_state: db::WoState) -> db::work_order::Data {
+        serde_json::from_value(json!({
+            "id": 1,
+            "created_at": "2023-06-02T00:00:00+00:00",
+            "updated_at": "2023-06-02T00:00:00+00:00",
+            "deleted_at": null,
+            "company_id": 1,
+            "mr_id": null,
+            "asset_id": 1,
+            "wo_name": "pump",
+            "wo_description": "replace the seal",
+            "wo_state": wo_state,
+            "planned_start": null,
+            "planned_end": null,
+            "started_at": null,
+            "completed_at": null,
+            "completion_notes": null,
+        }))
+        .unwrap()
+    }
+
+    fn allowed(action: WoAction, state: db::WoState) -> bool {
+        matches!(
+            (action, state),
+            (WoAction::Start, Planned | OnHold)
+                | (WoAction::Hold, InProgress)
+                | (WoAction::Complete, InProgress)
+                | (WoAction::Cancel, Planned | InProgress | OnHold)
+        )
+    }
+
+    #[test]
+    fn params_take_the_allowed_steps() {
+        for action in ACTIONS {
+            for state in STATES.into_iter().filter(|s| allowed(action, *s)) {
+                assert!(
+                    action.params(&work_order(state)).is_ok(),
+                    "{:?} from {:?}",
+                    action,
+                    state
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn params_reject_the_other_steps() {
+        for action in ACTIONS {
+            for state in STATES.into_iter().filter(|s| !allowed(action, *s)) {
+                assert!(
+                    matches!(
+                        action.params(&work_order(state)),
+                        Err(AppError::Custom {
+                            status_code: 409,
+                            ..
+                        })
+                    ),
+                    "{:?} from {:?}",
+                    action,
+                    state
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn only_unfinished_work_orders_are_editable() {
+        for state in [Planned, InProgress, OnHold] {
+            assert!(check_editable(&work_order(state)).is_ok());
+        }
+        for state in [Completed, Cancelled] {
+            assert!(check_editable(&work_order(state)).is_err());
+        }
+    }
+}